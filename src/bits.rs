@@ -1,132 +1,701 @@
+use std::ops::Range;
+
+use disasm::{NoTrace, Trace};
+
+mod bignum;
+mod bytecode;
+mod disasm;
+mod display;
+mod eval;
+mod expr;
+mod packed;
+mod random;
+mod stream;
+mod validate;
+mod visit;
+
+pub use bignum::{BigUint, ParseBigUintError};
+pub use bytecode::{Op, Program};
+pub use disasm::{disassemble, Field, FieldKind, Listing};
+pub use display::{Layout, PacketFormat};
+pub use eval::EvalError;
+pub use expr::{ExprError, ExprErrorKind};
+pub use packed::{BitReader, Bits};
+pub use random::{GeneratorConfig, PacketGenerator};
+pub use stream::{Decoded, StreamDecoder};
+pub use validate::{Violation, ViolationKind};
+pub use visit::{Fold, Visitor};
+
+/// A BITS packet tree. Parsing, encoding, traversal, evaluation and dropping
+/// work on trees of any depth; cloning, comparing and formatting recurse.
 #[derive(Debug, Clone)]
 pub struct Packet {
     version: u32,
     op_type: u32,
     contents: PacketContents,
+    span: Option<Range<usize>>,
 }
 
+/// Packets are equal when their trees are; where they came from in a
+/// transmission does not matter.
+impl PartialEq for Packet {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.op_type == other.op_type
+            && self.contents == other.contents
+    }
+}
+
+impl Eq for Packet {}
+
 impl Packet {
+    pub fn literal(version: u32, value: u64) -> Self {
+        Self {
+            version,
+            op_type: 4,
+            contents: PacketContents::Literal(value),
+            span: None,
+        }
+    }
+
+    /// A literal of any size. Stored as a plain `literal` if it fits in 64
+    /// bits.
+    pub fn wide_literal(version: u32, value: BigUint) -> Self {
+        let contents = match value.to_u64() {
+            Some(small) => PacketContents::Literal(small),
+            None => PacketContents::WideLiteral(value),
+        };
+        Self {
+            version,
+            op_type: 4,
+            contents,
+            span: None,
+        }
+    }
+
+    pub fn operator(version: u32, op_type: u32, packets: Vec<Packet>) -> Self {
+        Self {
+            version,
+            op_type,
+            contents: PacketContents::SubPackets(packets),
+            span: None,
+        }
+    }
+
+    /// The bits this packet occupied in the transmission it was parsed from,
+    /// or `None` if it was built in code.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The type ID: 4 for literals, the kind of operator otherwise.
+    pub fn type_id(&self) -> u32 {
+        self.op_type
+    }
+
+    pub fn contents(&self) -> &PacketContents {
+        &self.contents
+    }
+
+    pub fn is_literal(&self) -> bool {
+        !matches!(self.contents, PacketContents::SubPackets(_))
+    }
+
+    /// The sub-packets of an operator, or nothing for a literal.
+    pub fn sub_packets(&self) -> &[Packet] {
+        match &self.contents {
+            PacketContents::SubPackets(packets) => packets,
+            _ => &[],
+        }
+    }
+
     pub fn version_sum(&self) -> u32 {
-        self.version as u32
-            + match &self.contents {
-                PacketContents::Literal(_) => 0,
-                PacketContents::SubPackets(packets) => {
-                    packets.iter().map(Packet::version_sum).sum::<u32>()
-                }
+        struct VersionSum;
+        impl Fold for VersionSum {
+            type Output = u32;
+            fn literal(&mut self, packet: &Packet, _: &[usize]) -> u32 {
+                packet.version
+            }
+            fn operator(&mut self, packet: &Packet, _: &[usize], packets: Vec<u32>) -> u32 {
+                packet.version + packets.iter().sum::<u32>()
             }
+        }
+        self.fold(&mut VersionSum)
     }
 
+    /// Parses a packet from a transmission of one bit per `u8`, returning the
+    /// packet and the number of bits it used. Panics on malformed input; use
+    /// `try_parse` to handle those.
     pub fn parse(input: &[u8]) -> (Self, usize) {
-        let mut it = input.iter().copied();
-        let version = bin_to_u64(&mut it, 3) as u32;
-        let op_type = bin_to_u64(&mut it, 3) as u32;
-        let (contents, sz) = match op_type {
-            4 => PacketContents::parse_literal(&input[6..]),
-            _ => PacketContents::parse_operator(&input[6..]),
-        };
-        (
-            Self {
-                version,
-                op_type,
-                contents,
-            },
-            sz + 6,
-        )
+        match Self::try_parse(input) {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("{}", e),
+        }
     }
 
-    pub fn value(&self) -> u64 {
-        match &self.contents {
-            PacketContents::Literal(v) => *v,
-            PacketContents::SubPackets(packets) => match self.op_type {
-                0 => packets.iter().map(Packet::value).sum(),
-                1 => packets.iter().map(Packet::value).product(),
-                2 => packets.iter().map(Packet::value).min().unwrap(),
-                3 => packets.iter().map(Packet::value).max().unwrap(),
-                5 => {
-                    if packets[0].value() > packets[1].value() {
-                        1
-                    } else {
-                        0
+    pub fn try_parse(input: &[u8]) -> Result<(Self, usize), ParseError> {
+        let bits = Bits::from_bin(input);
+        let mut reader = bits.reader();
+        let packet = Self::read(&mut reader)?;
+        Ok((packet, reader.position()))
+    }
+
+    /// Parses a complete hex transmission. Any bits after the outermost packet
+    /// are padding and must be zero.
+    pub fn from_hex(input: &str) -> Result<Self, ParseError> {
+        let bits = Bits::from_hex(input)?;
+        let mut reader = bits.reader();
+        let packet = Self::read(&mut reader)?;
+        if let Some(offset) = reader.skip_zeros() {
+            return Err(ParseError::new(offset, &[], ParseErrorKind::TrailingBits));
+        }
+        Ok(packet)
+    }
+
+    /// Parses one packet at the reader's position, leaving the reader just
+    /// past its end.
+    pub fn read(reader: &mut BitReader) -> Result<Self, ParseError> {
+        Self::read_at(reader, &mut NoTrace)
+    }
+
+    /// Every field that is read is reported to `trace`.
+    fn read_at<T: Trace>(reader: &mut BitReader, trace: &mut T) -> Result<Self, ParseError> {
+        PartialPacket::default().resume(reader, trace)
+    }
+
+    /// Encodes the packet as a transmission of one bit per `u8`, without any
+    /// padding. The result parses back into an equal packet.
+    pub fn to_bin(&self) -> Result<Vec<u8>, EncodeError> {
+        self.to_bits().map(|bits| bits.to_bin())
+    }
+
+    /// Encodes the packet as a hex transmission, padding the last digit with
+    /// zero bits.
+    pub fn to_hex(&self) -> Result<String, EncodeError> {
+        self.to_bits().map(|bits| bits.to_hex())
+    }
+
+    pub fn to_bits(&self) -> Result<Bits, EncodeError> {
+        let mut out = Bits::new();
+        self.encode(&mut out)?;
+        Ok(out)
+    }
+
+    fn encode(&self, out: &mut Bits) -> Result<(), EncodeError> {
+        enum Step<'a> {
+            Packet(&'a Packet),
+            /// Fills in the length field at this offset once the sub-packets
+            /// after it have been written.
+            Length(usize),
+        }
+
+        let mut steps = vec![Step::Packet(self)];
+        while let Some(step) = steps.pop() {
+            let packet = match step {
+                Step::Packet(packet) => packet,
+                Step::Length(length_at) => {
+                    let length = out.len() - length_at - 15;
+                    if length >= 1 << 15 {
+                        return Err(EncodeError::TooLong(length));
                     }
+                    out.set(length_at, length as u64, 15);
+                    continue;
                 }
-                6 => {
-                    if packets[0].value() < packets[1].value() {
-                        1
-                    } else {
-                        0
-                    }
+            };
+            if packet.version >= 8 {
+                return Err(EncodeError::VersionOutOfRange(packet.version));
+            }
+            out.push(packet.version as u64, 3);
+            match &packet.contents {
+                PacketContents::Literal(_) | PacketContents::WideLiteral(_)
+                    if packet.op_type != 4 =>
+                {
+                    return Err(EncodeError::InvalidType(packet.op_type));
+                }
+                PacketContents::Literal(v) => {
+                    out.push(4, 3);
+                    let groups = (64 - v.leading_zeros() as usize).div_ceil(4);
+                    encode_literal(out, groups, |g| v >> (4 * g));
+                }
+                PacketContents::WideLiteral(v) => {
+                    out.push(4, 3);
+                    encode_literal(out, v.bits().div_ceil(4), |g| v.nibble(g));
                 }
-                7 => {
-                    if packets[0].value() == packets[1].value() {
-                        1
+                PacketContents::SubPackets(packets) => {
+                    if packet.op_type >= 8 || packet.op_type == 4 {
+                        return Err(EncodeError::InvalidType(packet.op_type));
+                    }
+                    out.push(packet.op_type as u64, 3);
+                    if packets.len() < 1 << 11 {
+                        // Counting sub-packets takes the fewest bits
+                        out.push(1, 1);
+                        out.push(packets.len() as u64, 11);
                     } else {
-                        0
+                        out.push(0, 1);
+                        steps.push(Step::Length(out.len()));
+                        out.push(0, 15);
                     }
+                    steps.extend(packets.iter().rev().map(Step::Packet));
                 }
-                _ => panic!("invalid operator type {}", self.op_type),
-            },
+            }
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-enum PacketContents {
+impl Drop for Packet {
+    fn drop(&mut self) {
+        let mut pending = match &mut self.contents {
+            PacketContents::SubPackets(packets) => std::mem::take(packets),
+            _ => return,
+        };
+        while let Some(mut packet) = pending.pop() {
+            if let PacketContents::SubPackets(packets) = &mut packet.contents {
+                pending.append(packets);
+            }
+        }
+    }
+}
+
+/// Writes the groups of a literal, most significant first. There is always at
+/// least one group, even for zero.
+fn encode_literal(out: &mut Bits, groups: usize, nibble: impl Fn(usize) -> u64) {
+    for g in (0..groups.max(1)).rev() {
+        out.push((g > 0) as u64, 1);
+        out.push(nibble(g), 4);
+    }
+}
+
+/// Reasons a `Packet` can not be written as a BITS transmission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// Versions have to fit in 3 bits.
+    VersionOutOfRange(u32),
+    /// Type IDs have to fit in 3 bits, and 4 is reserved for literals.
+    InvalidType(u32),
+    /// Too many sub-packets to count in 11 bits, and too long (in bits) to
+    /// measure in 15 bits.
+    TooLong(usize),
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::VersionOutOfRange(v) => write!(f, "version {} does not fit in 3 bits", v),
+            EncodeError::InvalidType(t) => write!(f, "invalid type ID {}", t),
+            EncodeError::TooLong(len) => {
+                write!(f, "sub-packets of {} bits do not fit a length field", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// What a packet carries: a literal value, or the sub-packets of an operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketContents {
     Literal(u64),
+    /// A literal that does not fit in 64 bits.
+    WideLiteral(BigUint),
     SubPackets(Vec<Packet>),
 }
 
 impl PacketContents {
-    fn parse_literal(input: &[u8]) -> (Self, usize) {
-        let mut input = input.iter().copied();
-        let (mut value, mut read) = (0, 0);
-        while let Some(cont) = input.next() {
-            value = (value << 4) + bin_to_u64(&mut input, 4);
-            read += 5;
-            if cont == 0 {
+    fn parse_literal<T: Trace>(
+        reader: &mut BitReader,
+        path: &[usize],
+        trace: &mut T,
+    ) -> Result<Self, ParseError> {
+        let mut value: u64 = 0;
+        let mut wide: Option<BigUint> = None;
+        loop {
+            let pos = reader.position();
+            let group = reader
+                .read(5)
+                .ok_or_else(|| ParseError::new(pos, path, ParseErrorKind::TruncatedLiteral))?;
+            let kind = FieldKind::LiteralGroup {
+                last: group & 0x10 == 0,
+            };
+            trace.field(pos, 5, kind, group & 0xf, path.len());
+            match &mut wide {
+                Some(big) => big.push_nibble(group),
+                None if value >> 60 != 0 => {
+                    let mut big = BigUint::from(value);
+                    big.push_nibble(group);
+                    wide = Some(big);
+                }
+                None => value = value << 4 | (group & 0xf),
+            }
+            if group & 0x10 == 0 {
                 break;
             }
         }
-        (PacketContents::Literal(value), read)
+        Ok(match wide {
+            Some(big) => PacketContents::WideLiteral(big),
+            None => PacketContents::Literal(value),
+        })
     }
-    fn parse_operator(input: &[u8]) -> (Self, usize) {
-        let mut it = input.iter().copied();
-        let length_type = bin_to_u64(&mut it, 1);
-        let mut packets = Vec::new();
-        let mut cursor = 0;
-        if length_type == 0 {
-            let length = bin_to_u64(&mut it, 15) as usize;
-            cursor += 16;
-            while cursor < length + 16 {
-                let (new_packet, parsed_length) = Packet::parse(&input[cursor..]);
-                packets.push(new_packet);
-                cursor += parsed_length;
+}
+
+/// A packet that has been read up to some point, with the operators whose
+/// sub-packets are still being read.
+#[derive(Debug, Clone, Default)]
+struct PartialPacket {
+    open: Vec<OpenOperator>,
+    /// Locates the next packet to read in the tree, for error reporting.
+    path: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct OpenOperator {
+    version: u32,
+    op_type: u32,
+    start: usize,
+    limit: Limit,
+    packets: Vec<Packet>,
+}
+
+impl PartialPacket {
+    /// Whether nothing has been read yet.
+    fn is_empty(&self) -> bool {
+        self.open.is_empty()
+    }
+
+    /// Reads on from where the last call stopped, until the outermost packet
+    /// is complete. When the input runs out, the reader is moved back to the
+    /// start of the header or literal that was cut off, which is all that has to be read
+    /// again once more input has arrived. Fields read twice are also traced
+    /// twice.
+    fn resume<T: Trace>(
+        &mut self,
+        reader: &mut BitReader,
+        trace: &mut T,
+    ) -> Result<Packet, ParseError> {
+        loop {
+            let pos = reader.position();
+            let mut done = match self.read_header(reader, trace) {
+                Err(e) if e.is_truncated() => {
+                    reader.seek(pos);
+                    return Err(e);
+                }
+                result => result?,
+            };
+
+            // Close every operator that has all its sub-packets, then descend
+            // into the next sub-packet
+            loop {
+                let top = match self.open.last_mut() {
+                    Some(top) => top,
+                    None => return Ok(done.unwrap()),
+                };
+                if let Some(packet) = done.take() {
+                    top.packets.push(packet);
+                    self.path.pop();
+                }
+                if top.limit.wants_more(top.packets.len(), reader.position()) {
+                    self.path.push(top.packets.len());
+                    break;
+                }
+                let top = self.open.pop().unwrap();
+                if let Limit::Bits { at, length, end } = top.limit {
+                    if reader.position() > end {
+                        return Err(ParseError::new(
+                            at,
+                            &self.path,
+                            ParseErrorKind::BadLength {
+                                declared: length,
+                                actual: reader.position() - at - 15,
+                            },
+                        ));
+                    }
+                }
+                done = Some(Packet {
+                    version: top.version,
+                    op_type: top.op_type,
+                    contents: PacketContents::SubPackets(top.packets),
+                    span: Some(top.start..reader.position()),
+                });
             }
+        }
+    }
+
+    /// Reads the header of the next packet, and all of it if it is a literal.
+    /// An operator is left open for its sub-packets.
+    fn read_header<T: Trace>(
+        &mut self,
+        reader: &mut BitReader,
+        trace: &mut T,
+    ) -> Result<Option<Packet>, ParseError> {
+        let path = &self.path;
+        let pos = reader.position();
+        let header = reader
+            .read(6)
+            .ok_or_else(|| ParseError::new(pos, path, ParseErrorKind::TruncatedHeader))?;
+        let version = (header >> 3) as u32;
+        let op_type = (header & 7) as u32;
+        trace.field(pos, 3, FieldKind::Version, version as u64, path.len());
+        trace.field(pos + 3, 3, FieldKind::TypeId, op_type as u64, path.len());
+        if op_type == 4 {
+            let contents = PacketContents::parse_literal(reader, path, trace)?;
+            return Ok(Some(Packet {
+                version,
+                op_type,
+                contents,
+                span: Some(pos..reader.position()),
+            }));
+        }
+        let limit = Limit::read(reader, path, trace)?;
+        self.open.push(OpenOperator {
+            version,
+            op_type,
+            start: pos,
+            limit,
+            packets: Vec::new(),
+        });
+        Ok(None)
+    }
+}
+
+/// How an operator says where its sub-packets end.
+#[derive(Debug, Clone)]
+enum Limit {
+    /// The sub-packets take `length` bits, from just after the length field
+    /// at `at` up to `end`.
+    Bits {
+        at: usize,
+        length: usize,
+        end: usize,
+    },
+    Count(usize),
+}
+
+impl Limit {
+    /// Reads the length type and length or count of an operator.
+    fn read<T: Trace>(
+        reader: &mut BitReader,
+        path: &[usize],
+        trace: &mut T,
+    ) -> Result<Self, ParseError> {
+        let pos = reader.position();
+        let truncated = || ParseError::new(pos, path, ParseErrorKind::TruncatedHeader);
+        let length_type = reader.read(1).ok_or_else(truncated)?;
+        trace.field(pos, 1, FieldKind::LengthType, length_type, path.len());
+        if length_type == 0 {
+            let length = reader.read(15).ok_or_else(truncated)? as usize;
+            trace.field(pos + 1, 15, FieldKind::Length, length as u64, path.len());
+            Ok(Limit::Bits {
+                at: pos + 1,
+                length,
+                end: reader.position() + length,
+            })
         } else {
-            let amount = bin_to_u64(&mut it, 11) as usize;
-            cursor += 12;
-            while packets.len() < amount {
-                let (new_packet, parsed_length) = Packet::parse(&input[cursor..]);
-                packets.push(new_packet);
-                cursor += parsed_length;
-            }
+            let amount = reader.read(11).ok_or_else(truncated)? as usize;
+            trace.field(pos + 1, 11, FieldKind::Count, amount as u64, path.len());
+            Ok(Limit::Count(amount))
+        }
+    }
+
+    /// Whether another sub-packet follows, given how many have been read and
+    /// where the reader is.
+    fn wants_more(&self, read: usize, position: usize) -> bool {
+        match *self {
+            Limit::Bits { end, .. } => position < end,
+            Limit::Count(amount) => read < amount,
         }
-        (PacketContents::SubPackets(packets), cursor)
     }
 }
 
+/// Why and where a transmission failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Bit offset into the transmission at which the problem was detected.
+    pub offset: usize,
+    /// Indices of the sub-packets leading from the outermost packet to the
+    /// one being parsed. Empty for the outermost packet itself.
+    pub path: Vec<usize>,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A character that is not a hex digit.
+    InvalidHex(char),
+    /// The input ended in the middle of a version, type ID or length field.
+    TruncatedHeader,
+    /// The input ended before the last group of a literal.
+    TruncatedLiteral,
+    /// The last sub-packet runs past the number of bits declared in the length
+    /// field.
+    BadLength { declared: usize, actual: usize },
+    /// Padding after the outermost packet contains a one.
+    TrailingBits,
+}
+
+impl ParseError {
+    /// Whether the transmission simply ended too soon, so that more input
+    /// could still make it parse.
+    pub fn is_truncated(&self) -> bool {
+        matches!(
+            self.kind,
+            ParseErrorKind::TruncatedHeader | ParseErrorKind::TruncatedLiteral
+        )
+    }
+
+    fn new(offset: usize, path: &[usize], kind: ParseErrorKind) -> Self {
+        Self {
+            offset,
+            path: path.to_vec(),
+            kind,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bit {}, packet ", self.offset)?;
+        write_path(&self.path, f)?;
+        write!(f, ": ")?;
+        match &self.kind {
+            ParseErrorKind::InvalidHex(ch) => write!(f, "invalid hex digit {:?}", ch),
+            ParseErrorKind::TruncatedHeader => write!(f, "transmission ends inside a header"),
+            ParseErrorKind::TruncatedLiteral => write!(f, "transmission ends inside a literal"),
+            ParseErrorKind::BadLength { declared, actual } => write!(
+                f,
+                "length field declares {} bits of sub-packets, but they use {}",
+                declared, actual
+            ),
+            ParseErrorKind::TrailingBits => write!(f, "padding contains non-zero bits"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Writes a path of sub-packet indices like `root.1.0`.
+fn write_path(path: &[usize], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "root")?;
+    for i in path {
+        write!(f, ".{}", i)?;
+    }
+    Ok(())
+}
+
 pub fn parse_hex_to_bin(input: &str) -> Vec<u8> {
-    input
-        .chars()
-        .map(|ch| u8::from_str_radix(&ch.to_string(), 16).expect("parse error"))
-        .flat_map(|bits| (0..4).rev().map(move |i| (bits >> i) & 1))
-        .collect()
-}
-
-fn bin_to_u64<T>(mut input: T, bits: usize) -> u64
-where
-    T: Iterator<Item = u8>,
-{
-    (0..bits)
-        .filter_map(|_| input.next())
-        .fold(0, |acc, x| acc << 1 | x as u64)
+    try_parse_hex_to_bin(input).expect("parse error")
+}
+
+pub fn try_parse_hex_to_bin(input: &str) -> Result<Vec<u8>, ParseError> {
+    Bits::from_hex(input).map(|bits| bits.to_bin())
+}
+
+/// Inverse of `parse_hex_to_bin`. Pads the last hex digit with zero bits.
+pub fn bin_to_hex(input: &[u8]) -> String {
+    Bits::from_bin(input).to_hex()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_literal() {
+        let packet = Packet::literal(6, 2021);
+        assert_eq!(packet.to_hex().unwrap(), "D2FE28");
+        assert_eq!(Packet::literal(0, 0).to_bin().unwrap().len(), 11);
+    }
+
+    #[test]
+    fn round_trip() {
+        let packet = Packet::operator(
+            1,
+            0,
+            vec![
+                Packet::operator(2, 1, vec![Packet::literal(3, 3), Packet::literal(4, 4)]),
+                Packet::operator(
+                    5,
+                    7,
+                    vec![Packet::literal(6, u64::MAX), Packet::literal(7, 0)],
+                ),
+            ],
+        );
+        let hex = packet.to_hex().unwrap();
+        let (parsed, len) = Packet::parse(&parse_hex_to_bin(&hex));
+        assert_eq!(parsed, packet);
+        assert_eq!(len, packet.to_bin().unwrap().len());
+    }
+
+    #[test]
+    fn round_trip_length_in_bits() {
+        let packet = Packet::operator(0, 0, vec![Packet::literal(0, 1); 2500]);
+        let bin = packet.to_bin().unwrap();
+        assert_eq!(bin[6], 0);
+        assert_eq!(Packet::parse(&bin), (packet, bin.len()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |hex| Packet::from_hex(hex).unwrap_err();
+        assert_eq!(err("D2FE2").kind, ParseErrorKind::TruncatedLiteral);
+        assert_eq!(err("D2FE2").offset, 16);
+        assert_eq!(err("D2FE29").kind, ParseErrorKind::TrailingBits);
+        assert_eq!(err("D2FE29").offset, 23);
+        assert_eq!(err("D2XE28").kind, ParseErrorKind::InvalidHex('X'));
+        assert_eq!(err("D2XE28").offset, 8);
+        assert_eq!(err("E").kind, ParseErrorKind::TruncatedHeader);
+
+        let packet = Packet::operator(1, 6, vec![Packet::literal(6, 10), Packet::literal(2, 20)]);
+        let bin = packet.to_bin().unwrap();
+        let truncated = Packet::try_parse(&bin[..bin.len() - 3]).unwrap_err();
+        assert_eq!(truncated.kind, ParseErrorKind::TruncatedLiteral);
+        assert_eq!(truncated.path, vec![1]);
+
+        // Declare one bit less than the two sub-packets use
+        let mut short = Bits::from_bin(&bin[..6]);
+        short.push(0, 1);
+        short.push(bin.len() as u64 - 19, 15);
+        let short = [short.to_bin(), bin[18..].to_vec()].concat();
+        let overrun = Packet::try_parse(&short).unwrap_err();
+        assert_eq!(overrun.offset, 7);
+        assert_eq!(
+            overrun.kind,
+            ParseErrorKind::BadLength {
+                declared: 26,
+                actual: 27
+            }
+        );
+
+        // Literals may be wider than 64 bits
+        let mut overflow = Bits::new();
+        overflow.push(0b000100, 6);
+        for _ in 0..17 {
+            overflow.push(0b11111, 5);
+        }
+        overflow.push(0, 5);
+        let overflow = Packet::read(&mut overflow.reader()).unwrap();
+        assert_eq!(overflow.big_value().bits(), 18 * 4);
+        assert_eq!(overflow.to_bits().unwrap().len(), 6 + 18 * 5);
+
+        let wide = Packet::literal(0, u64::MAX).to_hex().unwrap();
+        assert_eq!(
+            err(&format!("{}F", &wide[..4])).kind,
+            ParseErrorKind::TruncatedLiteral
+        );
+    }
+
+    #[test]
+    fn encode_errors() {
+        assert_eq!(
+            Packet::literal(8, 0).to_bin(),
+            Err(EncodeError::VersionOutOfRange(8))
+        );
+        assert_eq!(
+            Packet::operator(0, 4, vec![]).to_bin(),
+            Err(EncodeError::InvalidType(4))
+        );
+    }
 }
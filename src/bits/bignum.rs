@@ -0,0 +1,233 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+/// An unsigned integer of any size, for literals and results that do not fit
+/// in a `u64`. Only supports what evaluating packets needs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigUint {
+    // Least significant first, without trailing zeros
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.limbs[..] {
+            [] => Some(0),
+            [lo] => Some(lo as u64),
+            [lo, hi] => Some((hi as u64) << 32 | lo as u64),
+            _ => None,
+        }
+    }
+
+    /// The lowest 64 bits.
+    pub fn low_u64(&self) -> u64 {
+        let limb = |i| self.limbs.get(i).copied().unwrap_or(0) as u64;
+        limb(1) << 32 | limb(0)
+    }
+
+    /// Number of bits needed to write the number down, 0 for zero.
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(hi) => 32 * self.limbs.len() - hi.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    /// The `i`th group of four bits, counting from the least significant.
+    pub fn nibble(&self, i: usize) -> u64 {
+        let limb = self.limbs.get(i / 8).copied().unwrap_or(0);
+        (limb >> (4 * (i % 8)) & 0xf) as u64
+    }
+
+    /// Shifts in another group of four bits at the bottom, like a literal
+    /// group in a transmission.
+    pub fn push_nibble(&mut self, nibble: u64) {
+        self.mul_add_small(16, nibble as u32 & 0xf);
+    }
+
+    fn mul_add_small(&mut self, factor: u32, mut carry: u32) {
+        for limb in self.limbs.iter_mut() {
+            let wide = *limb as u64 * factor as u64 + carry as u64;
+            *limb = wide as u32;
+            carry = (wide >> 32) as u32;
+        }
+        if carry != 0 {
+            self.limbs.push(carry);
+        }
+        self.normalize();
+    }
+
+    /// Divides in place, returning the remainder.
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let wide = rem << 32 | *limb as u64;
+            *limb = (wide / divisor as u64) as u32;
+            rem = wide % divisor as u64;
+        }
+        self.normalize();
+        rem as u32
+    }
+
+    fn normalize(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> Self {
+        let mut big = Self {
+            limbs: vec![value as u32, (value >> 32) as u32],
+        };
+        big.normalize();
+        big
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let a = self.limbs.get(i).copied().unwrap_or(0) as u64;
+            let b = other.limbs.get(i).copied().unwrap_or(0) as u64;
+            let wide = a + b + carry;
+            limbs.push(wide as u32);
+            carry = wide >> 32;
+        }
+        limbs.push(carry as u32);
+        let mut sum = BigUint { limbs };
+        sum.normalize();
+        sum
+    }
+}
+
+impl Mul for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let wide = a as u64 * b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = wide as u32;
+                carry = wide >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        let mut product = BigUint { limbs };
+        product.normalize();
+        product
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(small) = self.to_u64() {
+            return write!(f, "{}", small);
+        }
+        // Peel off nine decimal digits at a time
+        let mut rest = self.clone();
+        let mut chunks = Vec::new();
+        while !rest.is_zero() {
+            chunks.push(rest.div_rem_small(1_000_000_000));
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBigUintError;
+
+impl fmt::Display for ParseBigUintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal number")
+    }
+}
+
+impl std::error::Error for ParseBigUintError {}
+
+impl FromStr for BigUint {
+    type Err = ParseBigUintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseBigUintError);
+        }
+        let mut big = Self::zero();
+        for ch in s.chars() {
+            let digit = ch.to_digit(10).ok_or(ParseBigUintError)?;
+            big.mul_add_small(10, digit);
+        }
+        Ok(big)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let max = BigUint::from(u64::MAX);
+        let sum = &max + &BigUint::from(1);
+        assert_eq!(sum.to_u64(), None);
+        assert_eq!(sum.bits(), 65);
+        assert_eq!(sum.to_string(), "18446744073709551616");
+        assert_eq!(
+            (&max * &max).to_string(),
+            "340282366920938463426481119284349108225"
+        );
+        assert!(sum > max);
+        assert_eq!((&sum * &BigUint::zero()), BigUint::zero());
+        assert_eq!("18446744073709551616".parse(), Ok(sum));
+        assert_eq!("12x".parse::<BigUint>(), Err(ParseBigUintError));
+    }
+
+    #[test]
+    fn nibbles() {
+        let mut big = BigUint::zero();
+        for _ in 0..20 {
+            big.push_nibble(0xa);
+        }
+        assert_eq!(big.bits(), 80);
+        assert_eq!(big.nibble(0), 0xa);
+        assert_eq!(big.nibble(19), 0xa);
+        assert_eq!(big.nibble(20), 0);
+        assert_eq!(big.low_u64(), 0xaaaa_aaaa_aaaa_aaaa);
+    }
+}
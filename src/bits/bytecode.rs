@@ -0,0 +1,203 @@
+use super::{Packet, PacketContents};
+
+/// One instruction of a compiled packet tree. Operators pop their arguments
+/// off the stack and push their result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Pushes the literal in the given slot of `Program::literals`.
+    Literal(usize),
+    Sum(usize),
+    Product(usize),
+    Min(usize),
+    Max(usize),
+    Gt,
+    Lt,
+    Eq,
+}
+
+/// A packet tree lowered to stack machine code, in post-order. Literals live
+/// in numbered slots, in the order they appear in the tree, so they can be
+/// substituted between runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    ops: Vec<Op>,
+    literals: Vec<u64>,
+    max_stack: usize,
+}
+
+impl Program {
+    /// Lowers a packet tree, which must pass `Packet::validate`. Literals
+    /// wider than 64 bits keep their lowest 64 bits, like in `Packet::value`.
+    pub fn compile(packet: &Packet) -> Self {
+        let mut program = Self {
+            ops: Vec::new(),
+            literals: Vec::new(),
+            max_stack: 0,
+        };
+        let mut depth = 0;
+        // Packets with the number of sub-packets already emitted
+        let mut pending = vec![(packet, 0)];
+        while let Some((packet, done)) = pending.pop() {
+            let op = match &packet.contents {
+                PacketContents::Literal(v) => {
+                    program.literals.push(*v);
+                    Op::Literal(program.literals.len() - 1)
+                }
+                PacketContents::WideLiteral(v) => {
+                    program.literals.push(v.low_u64());
+                    Op::Literal(program.literals.len() - 1)
+                }
+                PacketContents::SubPackets(packets) if done < packets.len() => {
+                    pending.push((packet, done + 1));
+                    pending.push((&packets[done], 0));
+                    continue;
+                }
+                PacketContents::SubPackets(packets) => {
+                    let n = packets.len();
+                    let (op, binary) = match packet.op_type {
+                        0 => (Op::Sum(n), false),
+                        1 => (Op::Product(n), false),
+                        2 => (Op::Min(n), false),
+                        3 => (Op::Max(n), false),
+                        5 => (Op::Gt, true),
+                        6 => (Op::Lt, true),
+                        7 => (Op::Eq, true),
+                        t => panic!("invalid operator type {}", t),
+                    };
+                    assert!(
+                        if binary { n == 2 } else { n > 0 },
+                        "operator type {} with {} sub-packets",
+                        packet.op_type,
+                        n
+                    );
+                    depth -= n;
+                    op
+                }
+            };
+            program.ops.push(op);
+            depth += 1;
+            program.max_stack = program.max_stack.max(depth);
+        }
+        program
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    pub fn literals(&self) -> &[u64] {
+        &self.literals
+    }
+
+    pub fn literals_mut(&mut self) -> &mut [u64] {
+        &mut self.literals
+    }
+
+    /// Runs the program, with the same wrapping arithmetic as `Packet::value`.
+    /// This allocates a new stack every time; use `run` with a reused stack
+    /// to avoid that.
+    pub fn value(&self) -> u64 {
+        self.run(&mut Vec::new())
+    }
+
+    /// Runs the program on a caller-provided stack. Once the stack has grown
+    /// to fit, running again does not allocate.
+    pub fn run(&self, stack: &mut Vec<u64>) -> u64 {
+        stack.clear();
+        stack.reserve(self.max_stack);
+        for op in &self.ops {
+            let (n, combine): (usize, fn(&[u64]) -> u64) = match *op {
+                Op::Literal(i) => {
+                    stack.push(self.literals[i]);
+                    continue;
+                }
+                Op::Sum(n) => (n, |args| args.iter().fold(0, |a, &b| a.wrapping_add(b))),
+                Op::Product(n) => (n, |args| args.iter().fold(1, |a, &b| a.wrapping_mul(b))),
+                Op::Min(n) => (n, |args| *args.iter().min().unwrap()),
+                Op::Max(n) => (n, |args| *args.iter().max().unwrap()),
+                Op::Gt => (2, |args| (args[0] > args[1]) as u64),
+                Op::Lt => (2, |args| (args[0] < args[1]) as u64),
+                Op::Eq => (2, |args| (args[0] == args[1]) as u64),
+            };
+            let args = stack.len() - n;
+            let result = combine(&stack[args..]);
+            stack.truncate(args);
+            stack.push(result);
+        }
+        stack.pop().expect("empty program")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: [&str; 12] = [
+        "8A004A801A8002F478",
+        "620080001611562C8802118E34",
+        "C0015000016115A2E0802F182340",
+        "A0016C880162017C3686B18A3D4780",
+        "C200B40A82",
+        "04005AC33890",
+        "880086C3E88112",
+        "CE00C43D881120",
+        "D8005AC2A8F0",
+        "F600BC2D8F",
+        "9C005AC2F8F0",
+        "9C0141080250320F1802104A08",
+    ];
+
+    #[test]
+    fn matches_value() {
+        let mut stack = Vec::new();
+        for hex in SAMPLES {
+            let packet = Packet::from_hex(hex).unwrap();
+            let program = Program::compile(&packet);
+            assert_eq!(program.run(&mut stack), packet.value(), "{}", hex);
+        }
+        let overflow: Packet = "product(18446744073709551615, 3, sum(max(2, 9), 1))"
+            .parse()
+            .unwrap();
+        assert_eq!(Program::compile(&overflow).value(), overflow.value());
+    }
+
+    #[test]
+    fn substitute() {
+        let packet: Packet = "sum(product(3, 4), gt(1, 2))".parse().unwrap();
+        let mut program = Program::compile(&packet);
+        assert_eq!(
+            program.ops(),
+            &[
+                Op::Literal(0),
+                Op::Literal(1),
+                Op::Product(2),
+                Op::Literal(2),
+                Op::Literal(3),
+                Op::Gt,
+                Op::Sum(2)
+            ]
+        );
+        assert_eq!(program.literals(), &[3, 4, 1, 2]);
+        assert_eq!(program.value(), 12);
+        program.literals_mut()[2] = 5;
+        assert_eq!(program.value(), 13);
+    }
+
+    #[test]
+    fn deep() {
+        // Deep enough to overflow the call stack if any step recursed
+        let mut packet = Packet::literal(0, 1);
+        for _ in 0..100_000 {
+            packet = Packet::operator(1, 0, vec![packet]);
+        }
+        let hex = packet.to_hex().unwrap();
+        drop(packet);
+        let packet = Packet::from_hex(&hex).unwrap();
+        assert_eq!(packet.validate(), Ok(()));
+        assert_eq!(packet.version_sum(), 100_000);
+        assert_eq!(packet.value(), 1);
+        let program = Program::compile(&packet);
+        assert_eq!(program.max_stack, 1);
+        assert_eq!(program.value(), 1);
+    }
+}
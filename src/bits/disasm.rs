@@ -0,0 +1,177 @@
+use std::fmt;
+
+use super::{display::operator_name, Bits, Packet, ParseError};
+
+/// Receives every field as `Packet::read_at` reads it.
+pub(super) trait Trace {
+    fn field(&mut self, offset: usize, width: usize, kind: FieldKind, value: u64, depth: usize);
+}
+
+/// For plain parsing, which does not look at the fields.
+pub(super) struct NoTrace;
+
+impl Trace for NoTrace {
+    #[inline(always)]
+    fn field(&mut self, _: usize, _: usize, _: FieldKind, _: u64, _: usize) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Version,
+    TypeId,
+    LengthType,
+    /// Total length in bits of the sub-packets.
+    Length,
+    /// Number of sub-packets.
+    Count,
+    /// Four bits of a literal, and whether they are the last four.
+    LiteralGroup {
+        last: bool,
+    },
+}
+
+/// One field of a transmission, as read by the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub offset: usize,
+    /// The field's bits as `0`s and `1`s.
+    pub raw: String,
+    pub kind: FieldKind,
+    pub value: u64,
+    /// How many operators the field's packet is nested in.
+    pub depth: usize,
+}
+
+/// Every field of a transmission in order, up to where parsing stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub fields: Vec<Field>,
+    /// Why parsing stopped early, if it did.
+    pub error: Option<ParseError>,
+}
+
+/// Lists the fields in a transmission of one bit per `u8`, as made by
+/// `parse_hex_to_bin`. Uses the same parser as `Packet::parse`, so a
+/// transmission that fails to parse is listed up to the failure.
+pub fn disassemble(input: &[u8]) -> Listing {
+    struct Collect<'a> {
+        input: &'a [u8],
+        fields: Vec<Field>,
+    }
+    impl Trace for Collect<'_> {
+        fn field(
+            &mut self,
+            offset: usize,
+            width: usize,
+            kind: FieldKind,
+            value: u64,
+            depth: usize,
+        ) {
+            let raw = self.input[offset..offset + width]
+                .iter()
+                .map(|&b| if b == 0 { '0' } else { '1' })
+                .collect();
+            self.fields.push(Field {
+                offset,
+                raw,
+                kind,
+                value,
+                depth,
+            });
+        }
+    }
+
+    let bits = Bits::from_bin(input);
+    let mut collect = Collect {
+        input,
+        fields: Vec::new(),
+    };
+    let error = Packet::read_at(&mut bits.reader(), &mut collect).err();
+    Listing {
+        fields: collect.fields,
+        error,
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>6}  {:<16} {:indent$}",
+            self.offset,
+            self.raw,
+            "",
+            indent = 2 * self.depth
+        )?;
+        match self.kind {
+            FieldKind::Version => write!(f, "version     {}", self.value),
+            FieldKind::TypeId => {
+                write!(f, "type        {}", self.value)?;
+                match operator_name(self.value as u32) {
+                    Some(name) => write!(f, " ({})", name),
+                    None if self.value == 4 => write!(f, " (literal)"),
+                    None => Ok(()),
+                }
+            }
+            FieldKind::LengthType => write!(f, "length type {}", self.value),
+            FieldKind::Length => write!(f, "length      {} bits", self.value),
+            FieldKind::Count => write!(f, "count       {} packets", self.value),
+            FieldKind::LiteralGroup { last: false } => write!(f, "group       {}", self.value),
+            FieldKind::LiteralGroup { last: true } => write!(f, "last group  {}", self.value),
+        }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for field in &self.fields {
+            writeln!(f, "{}", field)?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bits::parse_hex_to_bin;
+
+    #[test]
+    fn listing() {
+        let listing = disassemble(&parse_hex_to_bin("38006F45291200"));
+        assert_eq!(listing.error, None);
+        assert_eq!(
+            listing.to_string(),
+            "     0  001              version     1
+     3  110              type        6 (lt)
+     6  0                length type 0
+     7  000000000011011  length      27 bits
+    22  110                version     6
+    25  100                type        4 (literal)
+    28  01010              last group  10
+    33  010                version     2
+    36  100                type        4 (literal)
+    39  10001              group       1
+    44  00100              last group  4
+"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let listing = disassemble(&parse_hex_to_bin("EE00D40C823"));
+        // The third sub-packet's header is cut off after four bits
+        assert_eq!(listing.fields.len(), 10);
+        assert_eq!(
+            listing.fields[9].kind,
+            FieldKind::LiteralGroup { last: true }
+        );
+        assert_eq!(listing.fields[9].depth, 1);
+        let error = listing.error.unwrap();
+        assert_eq!(error.offset, 40);
+        assert_eq!(error.path, vec![2]);
+    }
+}
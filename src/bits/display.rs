@@ -0,0 +1,159 @@
+use std::fmt;
+
+use super::{Packet, PacketContents};
+
+/// How `PacketFormat` lays out a packet tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// On one line, like `sum(product(3, 4), max(1, 2))`.
+    Expression,
+    /// One packet per line, indented by depth and annotated with version,
+    /// type ID and the bits it was parsed from.
+    Tree,
+}
+
+/// Renders a packet according to a `Layout`. `Packet`'s own `Display` is the
+/// expression layout, or the expression layout with versions for `{:#}`.
+#[derive(Debug, Clone, Copy)]
+pub struct PacketFormat<'a> {
+    packet: &'a Packet,
+    layout: Layout,
+    versions: bool,
+}
+
+impl Packet {
+    pub fn format(&self, layout: Layout) -> PacketFormat<'_> {
+        PacketFormat {
+            packet: self,
+            layout,
+            versions: false,
+        }
+    }
+}
+
+impl<'a> PacketFormat<'a> {
+    /// Prefixes every packet in the expression layout with `v<N>:`, so the
+    /// output parses back into the same packet. The tree layout always shows
+    /// versions.
+    pub fn with_versions(mut self, versions: bool) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    fn expression(&self, packet: &Packet, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.versions {
+            write!(f, "v{}:", packet.version)?;
+        }
+        match &packet.contents {
+            PacketContents::Literal(v) => write!(f, "{}", v),
+            PacketContents::WideLiteral(v) => write!(f, "{}", v),
+            PacketContents::SubPackets(packets) => {
+                write_operator(packet.op_type, f)?;
+                write!(f, "(")?;
+                for (i, p) in packets.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    self.expression(p, f)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+
+    fn tree(&self, packet: &Packet, depth: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:indent$}", "", indent = 2 * depth)?;
+        match &packet.contents {
+            PacketContents::Literal(v) => write!(f, "{}", v)?,
+            PacketContents::WideLiteral(v) => write!(f, "{}", v)?,
+            PacketContents::SubPackets(_) => write_operator(packet.op_type, f)?,
+        }
+        write!(f, "  [v{} type {} bits ", packet.version, packet.op_type)?;
+        match &packet.span {
+            Some(span) => writeln!(f, "{}..{}]", span.start, span.end)?,
+            None => writeln!(f, "?]")?,
+        }
+        if let PacketContents::SubPackets(packets) = &packet.contents {
+            for p in packets {
+                self.tree(p, depth + 1, f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PacketFormat<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.layout {
+            Layout::Expression => self.expression(self.packet, f),
+            Layout::Tree => self.tree(self.packet, 0, f),
+        }
+    }
+}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.format(Layout::Expression)
+            .with_versions(f.alternate())
+            .fmt(f)
+    }
+}
+
+fn write_operator(op_type: u32, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match operator_name(op_type) {
+        Some(name) => write!(f, "{}", name),
+        None => write!(f, "op{}", op_type),
+    }
+}
+
+pub(super) fn operator_name(op_type: u32) -> Option<&'static str> {
+    match op_type {
+        0 => Some("sum"),
+        1 => Some("product"),
+        2 => Some("min"),
+        3 => Some("max"),
+        5 => Some("gt"),
+        6 => Some("lt"),
+        7 => Some("eq"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expression() {
+        let packet = Packet::operator(
+            1,
+            0,
+            vec![
+                Packet::operator(2, 1, vec![Packet::literal(3, 3), Packet::literal(4, 4)]),
+                Packet::operator(5, 3, vec![Packet::literal(6, 1), Packet::literal(7, 2)]),
+            ],
+        );
+        assert_eq!(packet.to_string(), "sum(product(3, 4), max(1, 2))");
+        assert_eq!(
+            format!("{:#}", packet),
+            "v1:sum(v2:product(v3:3, v4:4), v5:max(v6:1, v7:2))"
+        );
+    }
+
+    #[test]
+    fn tree() {
+        let packet = Packet::from_hex("38006F45291200").unwrap();
+        assert_eq!(
+            packet.format(Layout::Tree).to_string(),
+            "lt  [v1 type 6 bits 0..49]
+  10  [v6 type 4 bits 22..33]
+  20  [v2 type 4 bits 33..49]
+"
+        );
+        let built = Packet::operator(0, 7, vec![]);
+        assert_eq!(
+            built.format(Layout::Tree).to_string(),
+            "eq  [v0 type 7 bits ?]\n"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::num::Wrapping;
+
+use super::{write_path, BigUint, Fold, Packet, PacketContents};
+
+impl Packet {
+    /// Evaluates the expression, wrapping around on overflow. Literals wider
+    /// than 64 bits count with their lowest 64 bits.
+    ///
+    /// Like the other evaluations, this assumes the tree passes `validate`,
+    /// and panics if it does not.
+    pub fn value(&self) -> u64 {
+        match self.evaluate::<Wrapping<u64>>() {
+            Ok(Wrapping(value)) => value,
+            Err(e) => unreachable!("wrapping evaluation failed: {}", e),
+        }
+    }
+
+    /// Evaluates the expression, failing if a literal or any intermediate
+    /// result does not fit in 64 bits.
+    ///
+    /// Only overflow is reported. A tree that does not pass `validate` still
+    /// panics, like in `value`.
+    pub fn checked_value(&self) -> Result<u64, EvalError> {
+        self.evaluate::<Checked>().map(|Checked(value)| value)
+    }
+
+    /// Evaluates the expression without any limit on the size of numbers.
+    pub fn big_value(&self) -> BigUint {
+        match self.evaluate::<BigUint>() {
+            Ok(value) => value,
+            Err(e) => unreachable!("unbounded evaluation failed: {}", e),
+        }
+    }
+
+    fn evaluate<N: Number>(&self) -> Result<N, EvalError> {
+        self.fold(&mut Evaluate(std::marker::PhantomData))
+    }
+}
+
+struct Evaluate<N>(std::marker::PhantomData<N>);
+
+impl<N: Number> Fold for Evaluate<N> {
+    type Output = Result<N, EvalError>;
+
+    fn literal(&mut self, packet: &Packet, path: &[usize]) -> Self::Output {
+        match packet.contents() {
+            PacketContents::Literal(v) => Ok(N::from_u64(*v)),
+            PacketContents::WideLiteral(v) => N::from_big(v).ok_or_else(|| overflow(path)),
+            PacketContents::SubPackets(_) => unreachable!(),
+        }
+    }
+
+    fn operator(
+        &mut self,
+        packet: &Packet,
+        path: &[usize],
+        packets: Vec<Self::Output>,
+    ) -> Self::Output {
+        let values = packets.into_iter().collect::<Result<Vec<N>, _>>()?;
+        let mut values = values.into_iter();
+        let compare = |values: &mut std::vec::IntoIter<N>, holds: fn(&N, &N) -> bool| {
+            let (a, b) = (values.next().unwrap(), values.next().unwrap());
+            N::from_u64(holds(&a, &b) as u64)
+        };
+        Ok(match packet.type_id() {
+            0 => values.try_fold(N::from_u64(0), |acc, v| {
+                acc.add(v).ok_or_else(|| overflow(path))
+            })?,
+            1 => values.try_fold(N::from_u64(1), |acc, v| {
+                acc.mul(v).ok_or_else(|| overflow(path))
+            })?,
+            2 => values.min().unwrap(),
+            3 => values.max().unwrap(),
+            5 => compare(&mut values, N::gt),
+            6 => compare(&mut values, N::lt),
+            7 => compare(&mut values, N::eq),
+            t => panic!("invalid operator type {}", t),
+        })
+    }
+}
+
+fn overflow(path: &[usize]) -> EvalError {
+    EvalError {
+        path: path.to_vec(),
+    }
+}
+
+/// Evaluation failed because a number did not fit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    /// Indices of the sub-packets leading from the outermost packet to the one
+    /// whose value overflowed.
+    pub path: Vec<usize>,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "packet ")?;
+        write_path(&self.path, f)?;
+        write!(f, ": value does not fit in 64 bits")
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// The arithmetic that `evaluate` needs. `None` means overflow.
+trait Number: Ord + Sized {
+    fn from_u64(value: u64) -> Self;
+    fn from_big(value: &BigUint) -> Option<Self>;
+    fn add(self, other: Self) -> Option<Self>;
+    fn mul(self, other: Self) -> Option<Self>;
+}
+
+impl Number for Wrapping<u64> {
+    fn from_u64(value: u64) -> Self {
+        Wrapping(value)
+    }
+    fn from_big(value: &BigUint) -> Option<Self> {
+        Some(Wrapping(value.low_u64()))
+    }
+    fn add(self, other: Self) -> Option<Self> {
+        Some(self + other)
+    }
+    fn mul(self, other: Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Checked(u64);
+
+impl Number for Checked {
+    fn from_u64(value: u64) -> Self {
+        Checked(value)
+    }
+    fn from_big(value: &BigUint) -> Option<Self> {
+        value.to_u64().map(Checked)
+    }
+    fn add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Checked)
+    }
+    fn mul(self, other: Self) -> Option<Self> {
+        self.0.checked_mul(other.0).map(Checked)
+    }
+}
+
+impl Number for BigUint {
+    fn from_u64(value: u64) -> Self {
+        BigUint::from(value)
+    }
+    fn from_big(value: &BigUint) -> Option<Self> {
+        Some(value.clone())
+    }
+    fn add(self, other: Self) -> Option<Self> {
+        Some(&self + &other)
+    }
+    fn mul(self, other: Self) -> Option<Self> {
+        Some(&self * &other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow() {
+        let max = u64::MAX.to_string();
+        let sum: Packet = format!("sum({}, 1)", max).parse().unwrap();
+        assert_eq!(sum.value(), 0);
+        assert_eq!(sum.checked_value(), Err(EvalError { path: vec![] }));
+        assert_eq!(sum.big_value().to_string(), "18446744073709551616");
+
+        let nested: Packet = format!("max(1, product(2, {}))", max).parse().unwrap();
+        assert_eq!(nested.checked_value(), Err(EvalError { path: vec![1] }));
+
+        let fits: Packet = format!("min(sum({}, 1), 7)", max).parse().unwrap();
+        assert_eq!(fits.big_value().to_u64(), Some(7));
+        assert!(fits.checked_value().is_err());
+    }
+
+    #[test]
+    fn wide_literals() {
+        let wide: Packet = "gt(36893488147419103232, 18446744073709551616)"
+            .parse()
+            .unwrap();
+        assert_eq!(wide.big_value().to_u64(), Some(1));
+        assert_eq!(wide.checked_value(), Err(EvalError { path: vec![0] }));
+        assert_eq!(wide.value(), 0);
+
+        let parsed = Packet::from_hex(&wide.to_hex().unwrap()).unwrap();
+        assert_eq!(parsed, wide);
+        assert_eq!(
+            parsed.to_string(),
+            "gt(36893488147419103232, 18446744073709551616)"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use super::Packet;
+
+/// Parses the expression layout of `PacketFormat`, e.g.
+/// `v6:sum(product(3, 4), v2:max(1, 2))`. Packets without a `v<N>:` prefix
+/// get version 0.
+impl FromStr for Packet {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = ExprParser {
+            input: s,
+            chars: s.char_indices().peekable(),
+        };
+        let packet = parser.packet()?;
+        match parser.next_token() {
+            None => Ok(packet),
+            Some((pos, ch)) => Err(ExprError::new(pos, ExprErrorKind::Unexpected(ch))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    /// Byte offset into the expression.
+    pub pos: usize,
+    pub kind: ExprErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprErrorKind {
+    UnexpectedEnd,
+    Unexpected(char),
+    UnknownOperator(String),
+    /// A version or type ID that does not fit in 32 bits.
+    NumberTooLarge,
+}
+
+impl ExprError {
+    fn new(pos: usize, kind: ExprErrorKind) -> Self {
+        Self { pos, kind }
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {}: ", self.pos)?;
+        match &self.kind {
+            ExprErrorKind::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ExprErrorKind::Unexpected(ch) => write!(f, "unexpected {:?}", ch),
+            ExprErrorKind::UnknownOperator(name) => write!(f, "unknown operator {:?}", name),
+            ExprErrorKind::NumberTooLarge => write!(f, "number too large"),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+struct ExprParser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> ExprParser<'a> {
+    fn packet(&mut self) -> Result<Packet, ExprError> {
+        let (pos, word) = self.word()?;
+        if let Some(version) = word.strip_prefix('v') {
+            if self.peek_token() == Some(':') {
+                self.next_token();
+                let version = parse_number(version, pos + 1)?;
+                let mut packet = self.packet()?;
+                packet.version = version;
+                return Ok(packet);
+            }
+        }
+        if word.starts_with(|ch: char| ch.is_ascii_digit()) {
+            return Ok(Packet::wide_literal(0, parse_number(word, pos)?));
+        }
+        let op_type = match word {
+            "sum" => 0,
+            "product" => 1,
+            "min" => 2,
+            "max" => 3,
+            "gt" => 5,
+            "lt" => 6,
+            "eq" => 7,
+            _ => match word.strip_prefix("op") {
+                Some(id) if id.starts_with(|ch: char| ch.is_ascii_digit()) => {
+                    parse_number(id, pos + 2)?
+                }
+                _ => {
+                    return Err(ExprError::new(
+                        pos,
+                        ExprErrorKind::UnknownOperator(word.to_string()),
+                    ))
+                }
+            },
+        };
+        self.expect('(')?;
+        let mut packets = Vec::new();
+        if self.peek_token() == Some(')') {
+            self.next_token();
+        } else {
+            loop {
+                packets.push(self.packet()?);
+                match self.next_token() {
+                    Some((_, ',')) => (),
+                    Some((_, ')')) => break,
+                    Some((pos, ch)) => {
+                        return Err(ExprError::new(pos, ExprErrorKind::Unexpected(ch)))
+                    }
+                    None => return Err(self.end()),
+                }
+            }
+        }
+        Ok(Packet::operator(0, op_type, packets))
+    }
+
+    /// Reads a run of alphanumeric characters, returning it with its offset.
+    fn word(&mut self) -> Result<(usize, &'a str), ExprError> {
+        let start = match self.next_token() {
+            Some((pos, ch)) if ch.is_ascii_alphanumeric() => pos,
+            Some((pos, ch)) => return Err(ExprError::new(pos, ExprErrorKind::Unexpected(ch))),
+            None => return Err(self.end()),
+        };
+        let mut end = start + 1;
+        while let Some(&(pos, ch)) = self.chars.peek() {
+            if !ch.is_ascii_alphanumeric() {
+                break;
+            }
+            end = pos + 1;
+            self.chars.next();
+        }
+        Ok((start, &self.input[start..end]))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ExprError> {
+        match self.next_token() {
+            Some((_, ch)) if ch == expected => Ok(()),
+            Some((pos, ch)) => Err(ExprError::new(pos, ExprErrorKind::Unexpected(ch))),
+            None => Err(self.end()),
+        }
+    }
+
+    fn next_token(&mut self) -> Option<(usize, char)> {
+        self.chars.find(|(_, ch)| !ch.is_whitespace())
+    }
+
+    fn peek_token(&mut self) -> Option<char> {
+        while let Some(&(_, ch)) = self.chars.peek() {
+            if !ch.is_whitespace() {
+                return Some(ch);
+            }
+            self.chars.next();
+        }
+        None
+    }
+
+    fn end(&self) -> ExprError {
+        ExprError::new(self.input.len(), ExprErrorKind::UnexpectedEnd)
+    }
+}
+
+fn parse_number<T: FromStr>(digits: &str, pos: usize) -> Result<T, ExprError> {
+    if let Some(i) = digits.find(|ch: char| !ch.is_ascii_digit()) {
+        let ch = digits[i..].chars().next().unwrap();
+        return Err(ExprError::new(pos + i, ExprErrorKind::Unexpected(ch)));
+    }
+    digits
+        .parse()
+        .map_err(|_| ExprError::new(pos, ExprErrorKind::NumberTooLarge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let packet: Packet = "sum(product(3, 4), max(1, 2))".parse().unwrap();
+        assert_eq!(packet.value(), 14);
+        assert_eq!(packet.to_string(), "sum(product(3, 4), max(1, 2))");
+
+        let versioned = "v1:lt( v6:10 ,v2:20 )".parse::<Packet>().unwrap();
+        assert_eq!(versioned, Packet::from_hex("38006F45291200").unwrap());
+        assert_eq!(
+            Packet::from_hex(&versioned.to_hex().unwrap()),
+            Ok(versioned.clone())
+        );
+        assert_eq!(format!("{:#}", versioned).parse(), Ok(versioned));
+
+        assert_eq!("op9()".parse(), Ok(Packet::operator(0, 9, vec![])));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |s: &str| s.parse::<Packet>().unwrap_err();
+        assert_eq!(
+            err("sum(1, 2"),
+            ExprError::new(8, ExprErrorKind::UnexpectedEnd)
+        );
+        assert_eq!(
+            err("sum(1; 2)"),
+            ExprError::new(5, ExprErrorKind::Unexpected(';'))
+        );
+        assert_eq!(
+            err("add(1, 2)"),
+            ExprError::new(0, ExprErrorKind::UnknownOperator("add".to_string()))
+        );
+        assert_eq!(
+            err("v4294967296:1"),
+            ExprError::new(1, ExprErrorKind::NumberTooLarge)
+        );
+        assert_eq!(
+            err("v1:12a"),
+            ExprError::new(5, ExprErrorKind::Unexpected('a'))
+        );
+        assert_eq!(
+            err("1 2"),
+            ExprError::new(2, ExprErrorKind::Unexpected('2'))
+        );
+    }
+}
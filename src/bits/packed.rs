@@ -0,0 +1,242 @@
+use super::{ParseError, ParseErrorKind};
+
+/// A growable sequence of bits, packed eight to a byte with the most
+/// significant bit first, like the hex transmission itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_hex(input: &str) -> Result<Self, ParseError> {
+        let input = input.trim_end();
+        let mut bits = Self {
+            bytes: Vec::with_capacity(input.len().div_ceil(2)),
+            len: 0,
+        };
+        for (i, ch) in input.chars().enumerate() {
+            let digit = ch
+                .to_digit(16)
+                .ok_or_else(|| ParseError::new(4 * i, &[], ParseErrorKind::InvalidHex(ch)))?;
+            bits.push(digit as u64, 4);
+        }
+        Ok(bits)
+    }
+
+    /// Packs a transmission of one bit per `u8`, as made by `parse_hex_to_bin`.
+    pub fn from_bin(input: &[u8]) -> Self {
+        let mut bits = Self::new();
+        for chunk in input.chunks(64) {
+            let value = chunk.iter().fold(0, |acc, &b| acc << 1 | (b & 1) as u64);
+            bits.push(value, chunk.len());
+        }
+        bits
+    }
+
+    /// Unpacks into one bit per `u8`.
+    pub fn to_bin(&self) -> Vec<u8> {
+        (0..self.len)
+            .map(|i| self.bytes[i / 8] >> (7 - i % 8) & 1)
+            .collect()
+    }
+
+    /// Writes the bits as hex digits, padding the last one with zero bits.
+    pub fn to_hex(&self) -> String {
+        let mut reader = self.reader();
+        let mut hex = String::with_capacity(self.len.div_ceil(4));
+        while reader.remaining() > 0 {
+            let bits = reader.remaining().min(4);
+            let digit = reader.read(bits).unwrap() << (4 - bits);
+            hex.push(
+                std::char::from_digit(digit as u32, 16)
+                    .unwrap()
+                    .to_ascii_uppercase(),
+            );
+        }
+        hex
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends the lowest `bits` bits of `value`, most significant first.
+    pub fn push(&mut self, value: u64, bits: usize) {
+        for i in (0..bits).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            self.bytes[self.len / 8] |= ((value >> i) as u8 & 1) << (7 - self.len % 8);
+            self.len += 1;
+        }
+    }
+
+    /// Overwrites `bits` bits starting at `pos` with the lowest bits of
+    /// `value`. Used to fill in length fields after the fact.
+    pub fn set(&mut self, pos: usize, value: u64, bits: usize) {
+        assert!(pos + bits <= self.len, "setting bits past the end");
+        for (j, i) in (0..bits).rev().enumerate() {
+            let (byte, shift) = ((pos + j) / 8, 7 - (pos + j) % 8);
+            self.bytes[byte] = self.bytes[byte] & !(1 << shift) | ((value >> i) as u8 & 1) << shift;
+        }
+    }
+
+    /// Drops whole bytes from the front, covering at most the first `bits`
+    /// bits. Returns how many bits went.
+    pub fn drain_front(&mut self, bits: usize) -> usize {
+        let bytes = bits.min(self.len) / 8;
+        self.bytes.drain(..bytes);
+        self.len -= 8 * bytes;
+        8 * bytes
+    }
+
+    pub fn reader(&self) -> BitReader<'_> {
+        BitReader {
+            bytes: &self.bytes,
+            len: self.len,
+            pos: 0,
+            base: 0,
+        }
+    }
+}
+
+/// A cursor over `Bits` that reads fields of up to 64 bits at a time.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    len: usize,
+    pos: usize,
+    base: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Reports positions as if the underlying bits started at `base` in the
+    /// transmission, for when earlier bits have been dropped.
+    pub fn with_base(mut self, base: usize) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Bit offset of the next read from the start of the transmission.
+    pub fn position(&self) -> usize {
+        self.base + self.pos
+    }
+
+    /// Moves the cursor to an offset from the start of the transmission.
+    pub fn seek(&mut self, position: usize) {
+        assert!(
+            position >= self.base && position - self.base <= self.len,
+            "seeking out of bounds"
+        );
+        self.pos = position - self.base;
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.len - self.pos
+    }
+
+    /// Reads `bits` bits as a big-endian number, or `None` (without moving
+    /// the cursor) if there are not that many left.
+    pub fn read(&mut self, bits: usize) -> Option<u64> {
+        assert!(bits <= 64, "can not read more than 64 bits at once");
+        if bits > self.remaining() {
+            return None;
+        }
+        let mut value = 0;
+        let mut left = bits;
+        while left > 0 {
+            let offset = self.pos % 8;
+            let take = (8 - offset).min(left);
+            let chunk =
+                (self.bytes[self.pos / 8] as u64) >> (8 - offset - take) & ((1 << take) - 1);
+            value = value << take | chunk;
+            self.pos += take;
+            left -= take;
+        }
+        Some(value)
+    }
+
+    /// Skips over zero bits up to the end, returning the offset of the first
+    /// one bit if there is any.
+    pub fn skip_zeros(&mut self) -> Option<usize> {
+        while self.remaining() > 0 {
+            let bits = self.remaining().min(64);
+            let start = self.pos;
+            let chunk = self.read(bits).unwrap();
+            if chunk != 0 {
+                self.pos = start + chunk.leading_zeros() as usize - (64 - bits);
+                return Some(self.position());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_across_bytes() {
+        let bits = Bits::from_hex("D2FE28").unwrap();
+        let mut reader = bits.reader();
+        assert_eq!(reader.read(3), Some(6));
+        assert_eq!(reader.read(3), Some(4));
+        assert_eq!(reader.read(5), Some(0b10111));
+        assert_eq!(reader.read(10), Some(0b1111000101));
+        assert_eq!(reader.position(), 21);
+        assert_eq!(reader.read(4), None);
+        assert_eq!(reader.read(3), Some(0));
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn round_trips() {
+        let hex = "9C0141080250320F1802104A08";
+        let bits = Bits::from_hex(hex).unwrap();
+        assert_eq!(bits.len(), 4 * hex.len());
+        assert_eq!(bits.to_hex(), hex);
+        assert_eq!(Bits::from_bin(&bits.to_bin()), bits);
+
+        let mut long = Bits::new();
+        long.push(u64::MAX, 64);
+        long.push(0, 5);
+        long.set(60, 0, 8);
+        let mut reader = long.reader();
+        assert_eq!(reader.read(1), Some(1));
+        assert_eq!(reader.read(64), Some(u64::MAX << 5));
+    }
+
+    #[test]
+    fn skip_zeros() {
+        let bits = Bits::from_hex("000040").unwrap();
+        let mut reader = bits.reader();
+        assert_eq!(reader.skip_zeros(), Some(17));
+        assert_eq!(reader.position(), 17);
+        let mut reader = bits.reader();
+        reader.read(18);
+        assert_eq!(reader.skip_zeros(), None);
+        let mut reader = bits.reader().with_base(100);
+        assert_eq!(reader.skip_zeros(), Some(117));
+    }
+
+    #[test]
+    fn drain_front() {
+        let mut bits = Bits::from_hex("123456789").unwrap();
+        assert_eq!(bits.drain_front(23), 16);
+        assert_eq!(bits.to_hex(), "56789");
+        let mut reader = bits.reader().with_base(16);
+        reader.seek(20);
+        assert_eq!(reader.read(8), Some(0x67));
+        assert_eq!(reader.position(), 28);
+    }
+}
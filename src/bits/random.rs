@@ -0,0 +1,209 @@
+use super::{BigUint, Packet};
+
+/// Knobs for `PacketGenerator`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratorConfig {
+    /// Operators below this depth only get literals as sub-packets.
+    pub max_depth: usize,
+    /// Most sub-packets for sums, products, minimums and maximums.
+    /// Comparisons always get two.
+    pub max_fan_out: usize,
+    /// Widest literal in bits. Literals get a random width up to this, so
+    /// small ones are as likely as large ones. May exceed 64.
+    pub literal_bits: usize,
+    /// Relative weights of making an operator of type 0, 1, 2, 3, 5, 6 and 7
+    /// respectively.
+    pub operator_weights: [u32; 7],
+    /// Relative weight of making a literal above `max_depth`, against the sum
+    /// of `operator_weights`.
+    pub literal_weight: u32,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_fan_out: 4,
+            literal_bits: 32,
+            operator_weights: [1; 7],
+            literal_weight: 3,
+        }
+    }
+}
+
+/// Makes random packet trees that pass `Packet::validate`. The same seed and
+/// config always give the same packets.
+#[derive(Debug, Clone)]
+pub struct PacketGenerator {
+    config: GeneratorConfig,
+    rng: SplitMix64,
+}
+
+const OPERATOR_TYPES: [u32; 7] = [0, 1, 2, 3, 5, 6, 7];
+
+impl PacketGenerator {
+    pub fn new(seed: u64, config: GeneratorConfig) -> Self {
+        assert!(config.max_fan_out > 0, "operators need a sub-packet");
+        assert!(config.literal_bits > 0, "literals need a bit");
+        assert!(
+            config.literal_weight > 0 || config.operator_weights.iter().any(|&w| w > 0),
+            "all weights are zero"
+        );
+        Self {
+            config,
+            rng: SplitMix64(seed),
+        }
+    }
+
+    pub fn next_packet(&mut self) -> Packet {
+        self.packet(0)
+    }
+
+    fn packet(&mut self, depth: usize) -> Packet {
+        let version = self.rng.below(8) as u32;
+        let total: u32 = self.config.operator_weights.iter().sum();
+        if depth >= self.config.max_depth || total == 0 {
+            return self.literal(version);
+        }
+        let mut pick = self.rng.below((total + self.config.literal_weight) as u64) as u32;
+        let op_type = OPERATOR_TYPES
+            .iter()
+            .zip(self.config.operator_weights)
+            .find(|&(_, weight)| {
+                if pick < weight {
+                    true
+                } else {
+                    pick -= weight;
+                    false
+                }
+            })
+            .map(|(&op_type, _)| op_type);
+        let op_type = match op_type {
+            Some(op_type) => op_type,
+            None => return self.literal(version),
+        };
+        let fan_out = match op_type {
+            5..=7 => 2,
+            _ => 1 + self.rng.below(self.config.max_fan_out as u64) as usize,
+        };
+        let packets = (0..fan_out).map(|_| self.packet(depth + 1)).collect();
+        Packet::operator(version, op_type, packets)
+    }
+
+    fn literal(&mut self, version: u32) -> Packet {
+        let mut bits = 1 + self.rng.below(self.config.literal_bits as u64) as usize;
+        if bits <= 64 {
+            let value = self.rng.next() >> (64 - bits);
+            return Packet::literal(version, value);
+        }
+        // The short group goes first, so that it ends up most significant
+        let mut value = BigUint::zero();
+        let mut group = (bits - 1) % 4 + 1;
+        while bits > 0 {
+            value.push_nibble(self.rng.next() >> (64 - group));
+            bits -= group;
+            group = 4;
+        }
+        Packet::wide_literal(version, value)
+    }
+}
+
+impl Iterator for PacketGenerator {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        Some(self.next_packet())
+    }
+}
+
+/// Small, fast and good enough for test data.
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`, up to a negligible bias.
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let config = GeneratorConfig {
+            literal_bits: 100,
+            ..GeneratorConfig::default()
+        };
+        for packet in PacketGenerator::new(2021, config).take(200) {
+            assert_eq!(packet.validate(), Ok(()));
+            let parsed = Packet::from_hex(&packet.to_hex().unwrap()).unwrap();
+            assert_eq!(parsed, packet);
+            assert_eq!(parsed.big_value(), packet.big_value());
+        }
+    }
+
+    #[test]
+    fn literal_width() {
+        for literal_bits in [1, 3, 63, 64, 65, 67, 100] {
+            let config = GeneratorConfig {
+                literal_bits,
+                ..GeneratorConfig::default()
+            };
+            for seed in 0..50 {
+                for packet in PacketGenerator::new(seed, config.clone()).take(5) {
+                    let mut literals = Vec::new();
+                    let mut pending = vec![&packet];
+                    while let Some(p) = pending.pop() {
+                        if p.is_literal() {
+                            literals.push(p.big_value().bits());
+                        }
+                        pending.extend(p.sub_packets());
+                    }
+                    assert!(
+                        literals.iter().all(|&bits| bits <= literal_bits),
+                        "{:?} wider than {} bits",
+                        literals,
+                        literal_bits
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let a: Vec<_> = PacketGenerator::new(7, GeneratorConfig::default())
+            .take(10)
+            .collect();
+        let b: Vec<_> = PacketGenerator::new(7, GeneratorConfig::default())
+            .take(10)
+            .collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn config() {
+        let config = GeneratorConfig {
+            max_depth: 3,
+            max_fan_out: 10,
+            literal_bits: 4,
+            operator_weights: [0, 0, 0, 0, 0, 0, 1],
+            literal_weight: 0,
+        };
+        let packet = PacketGenerator::new(1, config).next_packet();
+        // A full binary tree of equality tests
+        assert_eq!(packet.to_string().matches("eq(").count(), 7);
+        assert!(packet.value() <= 1);
+    }
+}
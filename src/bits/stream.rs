@@ -0,0 +1,243 @@
+use super::disasm::NoTrace;
+use super::{Bits, Packet, ParseError, ParseErrorKind, PartialPacket};
+
+/// Decodes a sequence of top-level packets from a transmission that arrives in
+/// pieces. Each packet is padded with zero bits to a whole hex digit, after
+/// which the next one starts.
+///
+/// Reading picks up where the previous input ran out, so only a header or
+/// literal that was cut off is read again. Bits that have been read are
+/// dropped, so memory use is bounded by the packet tree being built.
+#[derive(Debug, Clone, Default)]
+pub struct StreamDecoder {
+    buffer: Bits,
+    /// Offset in the transmission of the first bit in `buffer`.
+    base: usize,
+    /// Offset in the transmission where reading goes on.
+    position: usize,
+    /// The packet being read at `position`.
+    partial: PartialPacket,
+    /// A packet that has been read, up to the padding that has not arrived.
+    complete: Option<Packet>,
+    /// Decoding can not succeed until the transmission is longer than this
+    /// many bits. Saves reading a cut off header again when nothing new has
+    /// arrived.
+    wait_for: usize,
+    /// The error that stopped decoding.
+    failed: Option<ParseError>,
+}
+
+/// The outcome of `StreamDecoder::next_packet`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Packet(Packet),
+    /// Nothing more can be decoded until more input is pushed.
+    NeedMoreInput,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends hex digits. On an invalid digit, everything before it is kept.
+    pub fn push_hex(&mut self, chunk: &str) -> Result<(), ParseError> {
+        for ch in chunk.chars().filter(|ch| !ch.is_whitespace()) {
+            let offset = self.base + self.buffer.len();
+            let digit = ch
+                .to_digit(16)
+                .ok_or_else(|| ParseError::new(offset, &[], ParseErrorKind::InvalidHex(ch)))?;
+            self.buffer.push(digit as u64, 4);
+        }
+        Ok(())
+    }
+
+    /// Appends raw bytes, most significant bit first.
+    pub fn push_bytes(&mut self, chunk: &[u8]) {
+        for &byte in chunk {
+            self.buffer.push(byte as u64, 8);
+        }
+    }
+
+    /// Decodes the next top-level packet, if all of it has arrived. Errors
+    /// other than running out of input are final: the decoder keeps reporting
+    /// them.
+    pub fn next_packet(&mut self) -> Result<Decoded, ParseError> {
+        if let Some(e) = &self.failed {
+            return Err(e.clone());
+        }
+        if self.base + self.buffer.len() <= self.wait_for {
+            return Ok(Decoded::NeedMoreInput);
+        }
+        let mut reader = self.buffer.reader().with_base(self.base);
+        reader.seek(self.position);
+        let packet = match self.complete.take() {
+            Some(packet) => packet,
+            None => match self.partial.resume(&mut reader, &mut NoTrace) {
+                Ok(packet) => packet,
+                Err(e) if e.is_truncated() => {
+                    self.wait_for = e.offset;
+                    self.advance(reader.position());
+                    return Ok(Decoded::NeedMoreInput);
+                }
+                Err(e) => {
+                    self.failed = Some(e.clone());
+                    return Err(e);
+                }
+            },
+        };
+
+        // Skip the padding up to the next hex digit
+        let end = reader.position();
+        let padding = (4 - end % 4) % 4;
+        match reader.read(padding) {
+            None => {
+                self.complete = Some(packet);
+                self.wait_for = end + padding - 1;
+                self.advance(end);
+                return Ok(Decoded::NeedMoreInput);
+            }
+            Some(0) => (),
+            Some(_) => {
+                reader.seek(end);
+                let offset = reader.skip_zeros().unwrap();
+                let e = ParseError::new(offset, &[], ParseErrorKind::TrailingBits);
+                self.failed = Some(e.clone());
+                return Err(e);
+            }
+        }
+        self.wait_for = 0;
+        self.advance(end + padding);
+        Ok(Decoded::Packet(packet))
+    }
+
+    /// Moves on to `position`, dropping the bits before it.
+    fn advance(&mut self, position: usize) {
+        self.position = position;
+        self.base += self.buffer.drain_front(position - self.base);
+    }
+
+    /// Ends the transmission, returning the packets that arrived but were not
+    /// taken with `next_packet` yet. A packet whose padding never arrived
+    /// counts as complete. Whatever follows the last packet has to be zero
+    /// padding; a packet that never completed is reported as truncated.
+    pub fn finish(mut self) -> Result<Vec<Packet>, ParseError> {
+        let mut packets = Vec::new();
+        while let Decoded::Packet(packet) = self.next_packet()? {
+            packets.push(packet);
+        }
+        packets.extend(self.complete.take());
+        let mut reader = self.buffer.reader().with_base(self.base);
+        reader.seek(self.position);
+        if self.partial.is_empty() && reader.clone().skip_zeros().is_none() {
+            return Ok(packets);
+        }
+        match self.partial.resume(&mut reader, &mut NoTrace) {
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("next_packet left a complete packet"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks() {
+        let first: Packet = "sum(1, max(2, 3))".parse().unwrap();
+        let second: Packet = "v5:lt(100, 1000)".parse().unwrap();
+        let hex = first.to_hex().unwrap() + &second.to_hex().unwrap();
+
+        let mut decoder = StreamDecoder::new();
+        let mut decoded = Vec::new();
+        for chunk in hex.as_bytes().chunks(3) {
+            decoder
+                .push_hex(std::str::from_utf8(chunk).unwrap())
+                .unwrap();
+            while let Decoded::Packet(p) = decoder.next_packet().unwrap() {
+                decoded.push(p);
+            }
+        }
+        assert_eq!(decoded, vec![first, second.clone()]);
+        assert_eq!(
+            decoded[1].span().unwrap().start,
+            4 * (hex.len() - second.to_hex().unwrap().len())
+        );
+        assert_eq!(decoder.next_packet(), Ok(Decoded::NeedMoreInput));
+        assert_eq!(decoder.finish(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn bytes_with_padding() {
+        let mut decoder = StreamDecoder::new();
+        // 38006F45291200 with its own trailing zero digits, as in the puzzle
+        decoder.push_bytes(&[0x38, 0x00, 0x6F, 0x45]);
+        assert_eq!(decoder.next_packet(), Ok(Decoded::NeedMoreInput));
+        decoder.push_bytes(&[0x29, 0x12, 0x00]);
+        let packet = match decoder.next_packet().unwrap() {
+            Decoded::Packet(p) => p,
+            Decoded::NeedMoreInput => panic!("packet should be complete"),
+        };
+        assert_eq!(packet.to_string(), "lt(10, 20)");
+        assert_eq!(decoder.next_packet(), Ok(Decoded::NeedMoreInput));
+        assert_eq!(decoder.finish(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn resumes() {
+        let mut packet = Packet::literal(0, 7);
+        for i in 0..1000 {
+            packet = Packet::operator(i % 8, i % 4, vec![Packet::literal(1, 1), packet]);
+        }
+        let hex = packet.to_hex().unwrap();
+
+        let mut decoder = StreamDecoder::new();
+        for (i, ch) in hex.char_indices() {
+            decoder.push_hex(&hex[i..i + ch.len_utf8()]).unwrap();
+            let decoded = decoder.next_packet().unwrap();
+            if i + 1 < hex.len() {
+                assert_eq!(decoded, Decoded::NeedMoreInput);
+            } else {
+                assert_eq!(decoded, Decoded::Packet(packet.clone()));
+            }
+            // Only the header or literal that was cut off is kept, so none of
+            // the packet can be read twice
+            assert!(
+                decoder.buffer.len() < 32,
+                "{} bits kept",
+                decoder.buffer.len()
+            );
+        }
+        assert_eq!(decoder.finish(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn finish_returns_unfetched() {
+        let first: Packet = "sum(1, 2)".parse().unwrap();
+        let second: Packet = "v3:7".parse().unwrap();
+        let mut decoder = StreamDecoder::new();
+        decoder.push_hex(&first.to_hex().unwrap()).unwrap();
+        assert_eq!(decoder.next_packet(), Ok(Decoded::Packet(first)));
+        decoder.push_hex(&second.to_hex().unwrap()).unwrap();
+        decoder.push_hex("00").unwrap();
+        assert_eq!(decoder.finish(), Ok(vec![second]));
+    }
+
+    #[test]
+    fn errors() {
+        let mut decoder = StreamDecoder::new();
+        decoder.push_hex("D2FE2").unwrap();
+        assert_eq!(decoder.next_packet(), Ok(Decoded::NeedMoreInput));
+        assert!(decoder.clone().finish().unwrap_err().is_truncated());
+        assert_eq!(
+            decoder.push_hex("9 X").unwrap_err(),
+            ParseError::new(24, &[], ParseErrorKind::InvalidHex('X'))
+        );
+        // The literal ends at bit 21, so bit 23 is padding that should be zero
+        assert_eq!(
+            decoder.next_packet().unwrap_err(),
+            ParseError::new(23, &[], ParseErrorKind::TrailingBits)
+        );
+    }
+}
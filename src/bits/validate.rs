@@ -0,0 +1,130 @@
+use std::fmt;
+
+use super::{write_path, Packet, PacketContents, Visitor};
+
+impl Packet {
+    /// Checks that the tree is something `value` can evaluate and `to_bits`
+    /// can encode, reporting every problem rather than just the first.
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        let mut validate = Validate(Vec::new());
+        self.walk(&mut validate);
+        if validate.0.is_empty() {
+            Ok(())
+        } else {
+            Err(validate.0)
+        }
+    }
+}
+
+struct Validate(Vec<Violation>);
+
+impl Visitor for Validate {
+    fn enter(&mut self, packet: &Packet, path: &[usize]) {
+        let mut report = |kind| {
+            self.0.push(Violation {
+                path: path.to_vec(),
+                kind,
+            })
+        };
+        if packet.version >= 8 {
+            report(ViolationKind::VersionOutOfRange(packet.version));
+        }
+        let packets = match &packet.contents {
+            PacketContents::Literal(_) | PacketContents::WideLiteral(_) => return,
+            PacketContents::SubPackets(packets) => packets,
+        };
+        match packet.op_type {
+            0..=3 if packets.is_empty() => report(ViolationKind::EmptyOperator),
+            5..=7 if packets.len() != 2 => report(ViolationKind::WrongArity {
+                expected: 2,
+                found: packets.len(),
+            }),
+            0..=3 | 5..=7 => (),
+            _ => report(ViolationKind::UnknownType(packet.op_type)),
+        }
+    }
+}
+
+/// A problem found by `Packet::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Indices of the sub-packets leading from the outermost packet to the
+    /// offending one.
+    pub path: Vec<usize>,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A comparison without exactly two sub-packets.
+    WrongArity { expected: usize, found: usize },
+    /// A sum, product, minimum or maximum without any sub-packets.
+    EmptyOperator,
+    /// An operator type ID that is not defined, including 4 (literal).
+    UnknownType(u32),
+    /// A version that does not fit in 3 bits.
+    VersionOutOfRange(u32),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "packet ")?;
+        write_path(&self.path, f)?;
+        write!(f, ": ")?;
+        match self.kind {
+            ViolationKind::WrongArity { expected, found } => {
+                write!(f, "expected {} sub-packets, found {}", expected, found)
+            }
+            ViolationKind::EmptyOperator => write!(f, "operator without sub-packets"),
+            ViolationKind::UnknownType(t) => write!(f, "unknown operator type {}", t),
+            ViolationKind::VersionOutOfRange(v) => write!(f, "version {} out of range", v),
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        let packet: Packet = "v7:sum(lt(1, 2), min(3), max(4, 5, 6))".parse().unwrap();
+        assert_eq!(packet.validate(), Ok(()));
+    }
+
+    #[test]
+    fn violations() {
+        let packet: Packet = "v8:sum(gt(1), max(), eq(1, min(), 3), op4(1))"
+            .parse()
+            .unwrap();
+        let violation = |path: &[usize], kind| Violation {
+            path: path.to_vec(),
+            kind,
+        };
+        assert_eq!(
+            packet.validate(),
+            Err(vec![
+                violation(&[], ViolationKind::VersionOutOfRange(8)),
+                violation(
+                    &[0],
+                    ViolationKind::WrongArity {
+                        expected: 2,
+                        found: 1
+                    }
+                ),
+                violation(&[1], ViolationKind::EmptyOperator),
+                violation(
+                    &[2],
+                    ViolationKind::WrongArity {
+                        expected: 2,
+                        found: 3
+                    }
+                ),
+                violation(&[2, 1], ViolationKind::EmptyOperator),
+                violation(&[3], ViolationKind::UnknownType(4)),
+            ])
+        );
+    }
+}
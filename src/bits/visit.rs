@@ -0,0 +1,145 @@
+use super::{Packet, PacketContents};
+
+/// Walks a packet tree top-down. Both methods get the path of sub-packet
+/// indices from the outermost packet, so `path.len()` is the depth.
+pub trait Visitor {
+    /// Called before any of the packet's sub-packets are visited.
+    fn enter(&mut self, _packet: &Packet, _path: &[usize]) {}
+    /// Called after all of the packet's sub-packets have been visited.
+    fn leave(&mut self, _packet: &Packet, _path: &[usize]) {}
+}
+
+/// Reduces a packet tree bottom-up, combining the results of the sub-packets
+/// of every operator.
+pub trait Fold {
+    type Output;
+
+    fn literal(&mut self, packet: &Packet, path: &[usize]) -> Self::Output;
+    fn operator(
+        &mut self,
+        packet: &Packet,
+        path: &[usize],
+        packets: Vec<Self::Output>,
+    ) -> Self::Output;
+}
+
+impl Packet {
+    pub fn walk<V: Visitor>(&self, visitor: &mut V) {
+        let mut path = Vec::new();
+        // Packets with the number of sub-packets already visited
+        let mut pending = vec![(self, 0)];
+        while let Some((packet, done)) = pending.pop() {
+            if done == 0 {
+                visitor.enter(packet, &path);
+            } else {
+                path.pop();
+            }
+            if let Some(next) = packet.sub_packets().get(done) {
+                pending.push((packet, done + 1));
+                pending.push((next, 0));
+                path.push(done);
+            } else {
+                visitor.leave(packet, &path);
+            }
+        }
+    }
+
+    pub fn fold<F: Fold>(&self, folder: &mut F) -> F::Output {
+        let mut path = Vec::new();
+        // Operators with the results of the sub-packets folded so far
+        let mut pending: Vec<(&Packet, Vec<F::Output>)> = Vec::new();
+        let mut packet = self;
+        loop {
+            let mut folded = match &packet.contents {
+                PacketContents::SubPackets(packets) if !packets.is_empty() => {
+                    pending.push((packet, Vec::with_capacity(packets.len())));
+                    packet = &packets[0];
+                    path.push(0);
+                    continue;
+                }
+                PacketContents::SubPackets(_) => folder.operator(packet, &path, Vec::new()),
+                PacketContents::Literal(_) | PacketContents::WideLiteral(_) => {
+                    folder.literal(packet, &path)
+                }
+            };
+            loop {
+                let Some((parent, results)) = pending.last_mut() else {
+                    return folded;
+                };
+                results.push(folded);
+                path.pop();
+                if let Some(next) = parent.sub_packets().get(results.len()) {
+                    path.push(results.len());
+                    packet = next;
+                    break;
+                }
+                let (parent, results) = pending.pop().unwrap();
+                folded = folder.operator(parent, &path, results);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct Stats {
+        depth: usize,
+        operators: HashMap<u32, usize>,
+        literals: HashMap<u64, usize>,
+    }
+
+    impl Visitor for Stats {
+        fn enter(&mut self, packet: &Packet, path: &[usize]) {
+            self.depth = self.depth.max(path.len());
+            match packet.contents() {
+                PacketContents::Literal(v) => *self.literals.entry(*v).or_insert(0) += 1,
+                PacketContents::WideLiteral(_) => (),
+                PacketContents::SubPackets(_) => {
+                    *self.operators.entry(packet.type_id()).or_insert(0) += 1
+                }
+            }
+        }
+    }
+
+    /// Replaces every operator whose sub-packets are all literals by a literal
+    /// of its value.
+    struct ConstantFold;
+
+    impl Fold for ConstantFold {
+        type Output = Packet;
+
+        fn literal(&mut self, packet: &Packet, _path: &[usize]) -> Packet {
+            packet.clone()
+        }
+        fn operator(&mut self, packet: &Packet, _path: &[usize], packets: Vec<Packet>) -> Packet {
+            let folded = Packet::operator(packet.version(), packet.type_id(), packets);
+            if folded.sub_packets().iter().all(Packet::is_literal) {
+                Packet::wide_literal(packet.version(), folded.big_value())
+            } else {
+                folded
+            }
+        }
+    }
+
+    #[test]
+    fn visit() {
+        let packet: Packet = "sum(product(3, 4), max(1, sum(1, 2)), 3)".parse().unwrap();
+        let mut stats = Stats::default();
+        packet.walk(&mut stats);
+        assert_eq!(stats.depth, 3);
+        assert_eq!(stats.operators[&0], 2);
+        assert_eq!(stats.operators[&1], 1);
+        assert_eq!(stats.literals[&1], 2);
+        assert_eq!(stats.literals[&3], 2);
+    }
+
+    #[test]
+    fn fold() {
+        let packet: Packet = "sum(product(3, 4), max(1, 2))".parse().unwrap();
+        assert_eq!(packet.fold(&mut ConstantFold), Packet::literal(0, 14));
+    }
+}
@@ -1,20 +1,49 @@
-use crate::bits::{parse_hex_to_bin, Packet};
+use std::fmt;
+
+use crate::bits::{Packet, ParseError, Violation};
+
+/// Why a transmission can not be solved: it does not parse, or it parses into
+/// a tree that can not be evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    Parse(ParseError),
+    Invalid(Vec<Violation>),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Parse(e) => write!(f, "{}", e),
+            InputError::Invalid(violations) => {
+                for (i, v) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
 
 #[aoc_generator(day16)]
-pub fn generator(input: &str) -> Vec<u8> {
-    parse_hex_to_bin(input)
+pub fn generator(input: &str) -> Result<Packet, InputError> {
+    let packet = Packet::from_hex(input).map_err(InputError::Parse)?;
+    packet.validate().map_err(InputError::Invalid)?;
+    Ok(packet)
 }
 
 #[aoc(day16, part1)]
-pub fn part1(input: &[u8]) -> u32 {
-    let (pack, _) = Packet::parse(input);
-    pack.version_sum()
+pub fn part1(input: &Packet) -> u32 {
+    input.version_sum()
 }
 
 #[aoc(day16, part2)]
-pub fn part2(input: &[u8]) -> u64 {
-    let (pack, _) = Packet::parse(input);
-    pack.value()
+pub fn part2(input: &Packet) -> u64 {
+    input.value()
 }
 
 #[cfg(test)]
@@ -23,23 +52,65 @@ mod tests {
 
     #[test]
     fn sample1() {
-        assert_eq!(part1(&parse_hex_to_bin("8A004A801A8002F478")), 16);
-        assert_eq!(part1(&parse_hex_to_bin("620080001611562C8802118E34")), 12);
-        assert_eq!(part1(&parse_hex_to_bin("C0015000016115A2E0802F182340")), 23);
+        assert_eq!(part1(&generator("8A004A801A8002F478").unwrap()), 16);
+        assert_eq!(part1(&generator("620080001611562C8802118E34").unwrap()), 12);
+        assert_eq!(
+            part1(&generator("C0015000016115A2E0802F182340").unwrap()),
+            23
+        );
         assert_eq!(
-            part1(&parse_hex_to_bin("A0016C880162017C3686B18A3D4780")),
+            part1(&generator("A0016C880162017C3686B18A3D4780").unwrap()),
             31
         );
     }
     #[test]
     fn sample2() {
-        assert_eq!(part2(&parse_hex_to_bin("C200B40A82")), 3);
-        assert_eq!(part2(&parse_hex_to_bin("04005AC33890")), 54);
-        assert_eq!(part2(&parse_hex_to_bin("880086C3E88112")), 7);
-        assert_eq!(part2(&parse_hex_to_bin("CE00C43D881120")), 9);
-        assert_eq!(part2(&parse_hex_to_bin("D8005AC2A8F0")), 1);
-        assert_eq!(part2(&parse_hex_to_bin("F600BC2D8F")), 0);
-        assert_eq!(part2(&parse_hex_to_bin("9C005AC2F8F0")), 0);
-        assert_eq!(part2(&parse_hex_to_bin("9C0141080250320F1802104A08")), 1);
+        assert_eq!(part2(&generator("C200B40A82").unwrap()), 3);
+        assert_eq!(part2(&generator("04005AC33890").unwrap()), 54);
+        assert_eq!(part2(&generator("880086C3E88112").unwrap()), 7);
+        assert_eq!(part2(&generator("CE00C43D881120").unwrap()), 9);
+        assert_eq!(part2(&generator("D8005AC2A8F0").unwrap()), 1);
+        assert_eq!(part2(&generator("F600BC2D8F").unwrap()), 0);
+        assert_eq!(part2(&generator("9C005AC2F8F0").unwrap()), 0);
+        assert_eq!(part2(&generator("9C0141080250320F1802104A08").unwrap()), 1);
+    }
+    #[test]
+    fn corrupt() {
+        assert!(matches!(generator("D2FE2"), Err(InputError::Parse(_))));
+        assert!(matches!(generator("D2FE29"), Err(InputError::Parse(_))));
+
+        // These parse, but part 2 can not evaluate them
+        let one_sided = Packet::operator(0, 5, vec![Packet::literal(0, 1)]);
+        let empty_min = Packet::operator(0, 0, vec![Packet::operator(0, 2, vec![])]);
+        for packet in [one_sided, empty_min] {
+            let error = generator(&packet.to_hex().unwrap()).unwrap_err();
+            assert_eq!(error, InputError::Invalid(packet.validate().unwrap_err()));
+        }
+    }
+    #[test]
+    fn expressions() {
+        let eval = |expr: &str| part2(&expr.parse().unwrap());
+        assert_eq!(eval("sum(1, 2)"), 3);
+        assert_eq!(eval("eq(sum(1, 3), product(2, 2))"), 1);
+        assert_eq!(eval("min(gt(5, 15), 7, 8, 9)"), 0);
+    }
+    #[test]
+    fn generated() {
+        use crate::bits::{GeneratorConfig, PacketGenerator};
+        let config = GeneratorConfig {
+            max_depth: 8,
+            ..GeneratorConfig::default()
+        };
+        for packet in PacketGenerator::new(16, config).take(20) {
+            let input = generator(&packet.to_hex().unwrap()).unwrap();
+            assert_eq!(part1(&input), packet.version_sum());
+            assert_eq!(part2(&input), packet.value());
+        }
+    }
+    #[test]
+    fn encoded() {
+        let sum = Packet::operator(0, 0, vec![Packet::literal(0, 1), Packet::literal(0, 2)]);
+        let product = Packet::operator(0, 1, vec![sum, Packet::literal(0, 5)]);
+        assert_eq!(part2(&generator(&product.to_hex().unwrap()).unwrap()), 15);
     }
 }
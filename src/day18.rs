@@ -1,115 +1,104 @@
-type SnailToken = u8;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use crate::snailfish::{ParseSnailError, SnailNumber, SnailOverflow, SnailRules};
 
 #[aoc_generator(day18)]
-pub fn parse_snail_numbers(input: &str) -> Vec<Vec<SnailToken>> {
-    input
-        .lines()
-        .map(|line| {
-            line.bytes()
-                .filter_map(|ch| match ch {
-                    b'[' | b']' => Some(ch),
-                    b',' => None,
-                    _ => Some(ch - b'0'),
-                })
-                .collect()
-        })
-        .collect()
+pub fn parse_snail_numbers(input: &str) -> Result<Vec<SnailNumber>, ParseSnailError> {
+    SnailNumber::parse_homework(input)
 }
 
 #[aoc(day18, part1)]
-pub fn part1(input: &[Vec<SnailToken>]) -> u64 {
-    let mut num = input[0].to_owned();
-    for other in input.iter().skip(1) {
-        num = add(&num, other);
-        while reduce(&mut num) {}
-    }
-    magnitude(&num, 0).0
+pub fn part1(input: &[SnailNumber]) -> Result<u64, SnailOverflow> {
+    input.iter().sum::<SnailNumber>().magnitude()
 }
 
 #[aoc(day18, part2)]
-pub fn part2(input: &[Vec<SnailToken>]) -> u64 {
-    let mut largest = 0;
-    for i in input {
-        for j in input {
-            let mut num = add(i, j);
-            while reduce(&mut num) {}
-            largest = largest.max(magnitude(&num, 0).0);
-        }
-    }
-    largest
+pub fn part2(input: &[SnailNumber]) -> Result<u64, SnailOverflow> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    largest_pair_magnitude(input, &SnailRules::default(), threads, None)
 }
 
-fn add(x: &[SnailToken], y: &[SnailToken]) -> Vec<SnailToken> {
-    let mut z = vec![b'['];
-    z.extend_from_slice(x);
-    z.extend_from_slice(y);
-    z.push(b']');
-    z
-}
-
-fn reduce(num: &mut Vec<SnailToken>) -> bool {
-    let mut nesting = 0;
-    for i in 0..num.len() {
-        match num[i] {
-            b'[' => {
-                nesting += 1;
-                if nesting > 4 {
-                    reduce_nested(num, i);
-                    return true;
+/// The largest magnitude of the sum of two different numbers from `input`, in
+/// either order, or 0 if there are fewer than two. Both adding and magnitude
+/// follow `rules`. Rows of the pair table are handed out to `threads` workers.
+///
+/// Rows and columns go from the largest total of regular numbers down, and a
+/// pair is skipped once `SnailRules::magnitude_bound` of their totals can not
+/// beat the largest magnitude so far. The rest of its row can not either.
+///
+/// If `upper_bound` is given, the search stops as soon as a sum reaches it.
+/// It has to be a true upper bound for the answer to stay exact. If any sum
+/// overflows, the search stops with an error.
+pub fn largest_pair_magnitude(
+    input: &[SnailNumber],
+    rules: &SnailRules,
+    threads: usize,
+    upper_bound: Option<u64>,
+) -> Result<u64, SnailOverflow> {
+    let next_row = AtomicUsize::new(0);
+    let largest = AtomicU64::new(0);
+    let done = AtomicBool::new(false);
+    let overflowed = AtomicBool::new(false);
+    let bound = upper_bound.unwrap_or(u64::MAX);
+    let mut order: Vec<(u64, usize)> = input
+        .iter()
+        .enumerate()
+        .map(|(i, num)| (regular_total(num), i))
+        .collect();
+    order.sort_unstable_by(|a, b| b.cmp(a));
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let row = next_row.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(row_total, i)) = order.get(row) else {
+                        break;
+                    };
+                    for &(total, j) in &order {
+                        if i == j {
+                            continue;
+                        }
+                        // A bound too large for a `u64` prunes nothing
+                        let reachable = rules
+                            .magnitude_bound(row_total.saturating_add(total))
+                            .unwrap_or(u64::MAX);
+                        if reachable <= largest.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        let magnitude = input[i]
+                            .clone()
+                            .add_with(input[j].clone(), rules)
+                            .and_then(|sum| sum.magnitude_with(rules));
+                        let magnitude = match magnitude {
+                            Ok(magnitude) => magnitude,
+                            Err(SnailOverflow) => {
+                                overflowed.store(true, Ordering::Relaxed);
+                                done.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                        };
+                        largest.fetch_max(magnitude, Ordering::Relaxed);
+                        if magnitude >= bound {
+                            done.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                    }
                 }
-            }
-            b']' => nesting -= 1,
-            _ => (),
-        }
-    }
-    for i in 0..num.len() {
-        match num[i] {
-            b'[' | b']' => (),
-            _ => {
-                if num[i] > 9 {
-                    reduce_split(num, i);
-                    return true;
-                }
-            }
-        }
-    }
-    false
-}
-
-fn reduce_nested(num: &mut Vec<SnailToken>, i: usize) {
-    // i is the opening bracket
-    // find the first regular number to the left of i, and add num[i+1] to it
-    for j in (0..i).rev() {
-        if num[j] < 90 {
-            num[j] += num[i + 1];
-            break;
-        }
-    }
-    // find the first regular number to the right of i+3, and add num[i+2] to it
-    for j in i + 4..num.len() {
-        if num[j] < 90 {
-            num[j] += num[i + 2];
-            break;
+            });
         }
+    });
+    if overflowed.into_inner() {
+        return Err(SnailOverflow);
     }
-    // replace the current pair by 0
-    num.splice(i..i + 4, [0]);
+    Ok(largest.into_inner())
 }
 
-fn reduce_split(num: &mut Vec<SnailToken>, i: usize) {
-    // i is the offending literal
-    num.splice(i..i + 1, [b'[', num[i] / 2, (num[i] + 1) / 2, b']']);
-}
-
-fn magnitude(num: &[SnailToken], i: usize) -> (u64, usize) {
-    match num[i] {
-        b'[' => {
-            let (left, consumed_left) = magnitude(num, i + 1);
-            let (right, consumed_right) = magnitude(num, i + 1 + consumed_left);
-            (3 * left + 2 * right, consumed_left + consumed_right + 2)
-        }
-        b']' => panic!("can not compute magnitude of ]"),
-        _ => (num[i] as u64, 1),
+/// The sum of the regular numbers in `num`.
+fn regular_total(num: &SnailNumber) -> u64 {
+    match num {
+        SnailNumber::Regular(v) => *v,
+        SnailNumber::Pair(left, right) => regular_total(left).saturating_add(regular_total(right)),
     }
 }
 
@@ -128,13 +117,91 @@ mod tests {
 [[[5,[7,4]],7],1]
 [[[[4,2],2],6],[8,7]]";
 
+    const HOMEWORK: &str = "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[5,[2,8]],4],[5,[[9,9],0]]]
+[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
+[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]
+[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]
+[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]
+[[[[5,4],[7,7]],8],[[8,3],8]]
+[[9,3],[[9,9],[6,[4,9]]]]
+[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
+[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
+
     #[test]
     fn sample1() {
-        assert_eq!(part1(&parse_snail_numbers("[[1,2],[[3,4],5]]")), 143);
-        assert_eq!(part1(&parse_snail_numbers(TEST_INPUT)), 3488);
+        assert_eq!(
+            part1(&parse_snail_numbers("[[1,2],[[3,4],5]]").unwrap()),
+            Ok(143)
+        );
+        assert_eq!(part1(&parse_snail_numbers(TEST_INPUT).unwrap()), Ok(3488));
+        assert_eq!(part1(&parse_snail_numbers(HOMEWORK).unwrap()), Ok(4140));
     }
     #[test]
     fn sample2() {
-        assert_eq!(part2(&parse_snail_numbers(TEST_INPUT)), 3993);
+        assert_eq!(part2(&parse_snail_numbers(HOMEWORK).unwrap()), Ok(3993));
+    }
+    #[test]
+    fn bound() {
+        let homework = parse_snail_numbers(HOMEWORK).unwrap();
+        let rules = SnailRules::default();
+        for a in &homework {
+            for b in &homework {
+                let magnitude = (a + b).magnitude().unwrap();
+                let total = regular_total(a) + regular_total(b);
+                assert!(magnitude <= rules.magnitude_bound(total).unwrap());
+            }
+        }
+    }
+    #[test]
+    fn pair_search() {
+        let homework = parse_snail_numbers(HOMEWORK).unwrap();
+        let rules = SnailRules::default();
+        for threads in [1, 3, 8] {
+            assert_eq!(
+                largest_pair_magnitude(&homework, &rules, threads, None),
+                Ok(3993)
+            );
+            assert_eq!(
+                largest_pair_magnitude(&homework, &rules, threads, Some(3993)),
+                Ok(3993)
+            );
+        }
+        // Sums of two small numbers can not beat those with the large one,
+        // and are skipped
+        let mixed = parse_snail_numbers(
+            "[[[[9,9],[9,9]],[[9,9],[9,9]]],[[[9,9],[9,9]],[[9,9],[9,9]]]]\n[1,1]\n[2,1]\n[1,2]",
+        )
+        .unwrap();
+        let mut expected = 0;
+        for (i, a) in mixed.iter().enumerate() {
+            for (j, b) in mixed.iter().enumerate() {
+                if i != j {
+                    expected = expected.max((a + b).magnitude().unwrap());
+                }
+            }
+        }
+        assert!(expected > rules.magnitude_bound(6).unwrap());
+        for threads in [1, 3] {
+            assert_eq!(
+                largest_pair_magnitude(&mixed, &rules, threads, None),
+                Ok(expected)
+            );
+        }
+
+        // A number is never added to itself
+        let one = parse_snail_numbers("[[[[5,0],[7,4]],[5,5]],[6,6]]").unwrap();
+        assert_eq!(largest_pair_magnitude(&one, &rules, 4, None), Ok(0));
+
+        let huge: Vec<SnailNumber> = vec!["[6148914691236517206,1]".parse().unwrap(); 2];
+        let none = SnailRules {
+            explode_depth: 10,
+            split_threshold: u64::MAX,
+            ..rules
+        };
+        assert_eq!(
+            largest_pair_magnitude(&huge, &none, 2, None),
+            Err(SnailOverflow)
+        );
     }
 }
@@ -1,10 +1,6 @@
-use euclid::Angle;
-type Point3D = euclid::default::Point3D<i32>;
-type Vector3D = euclid::default::Vector3D<i32>;
-type Rotation3D = euclid::default::Rotation3D<f64>;
+use crate::scanner::{align_scanners, AlignConfig, AlignError, Point, ScannerMap};
 
-#[aoc_generator(day19)]
-pub fn parse_scanners(input: &str) -> Vec<Vec<Point3D>> {
+pub fn parse_scanners(input: &str) -> Vec<Vec<Point<3>>> {
     let mut scanners = Vec::new();
     let input = input.lines().filter(|line| !line.is_empty());
     for line in input {
@@ -12,148 +8,50 @@ pub fn parse_scanners(input: &str) -> Vec<Vec<Point3D>> {
             scanners.push(Vec::new());
         } else {
             let mut p = line.split(',');
-            scanners.last_mut().unwrap().push(Point3D::from([
+            scanners.last_mut().unwrap().push([
                 p.next().map(str::parse).unwrap().unwrap(),
                 p.next().map(str::parse).unwrap().unwrap(),
                 p.next().map(str::parse).unwrap().unwrap(),
-            ]));
+            ]);
         }
     }
     scanners
 }
 
-#[aoc(day19, part1)]
-pub fn part1(input: &[Vec<Point3D>]) -> usize {
-    let mut input = input.to_owned();
-    let mut reference = input.remove(0);
-    while !input.is_empty() {
-        let mut ok = false;
-        for i in 0..input.len() {
-            if let Some((rot, vec)) = has_overlap_rot(&reference, &input[i]) {
-                let transformed: Vec<_> = input
-                    .swap_remove(i)
-                    .iter()
-                    .map(|p| rot.transform_point3d(p.cast()).round().cast() + vec)
-                    .collect();
-                reference.extend_from_slice(&transformed);
-                reference
-                    .sort_unstable_by(|a, b| a.x.cmp(&b.x).then(a.y.cmp(&b.y)).then(a.z.cmp(&b.z)));
-                reference.dedup();
-                ok = true;
-                break;
-            }
-        }
-        if !ok {
-            panic!("will not converge");
-        }
+#[aoc_generator(day19)]
+pub fn generator(input: &str) -> Result<ScannerMap<3>, AlignError> {
+    let map = align_scanners(&parse_scanners(input), &AlignConfig::default());
+    let disconnected = map.disconnected();
+    if disconnected.is_empty() {
+        Ok(map)
+    } else {
+        Err(AlignError { disconnected })
     }
-    reference.len()
+}
+
+#[aoc(day19, part1)]
+pub fn part1(map: &ScannerMap<3>) -> usize {
+    map.beacons.len()
 }
 
 #[aoc(day19, part2)]
-pub fn part2(input: &[Vec<Point3D>]) -> i32 {
+pub fn part2(map: &ScannerMap<3>) -> i32 {
     // Maximum manhattan distance between sensors
-    let mut input = input.to_owned();
-    let mut scanner_pos = Vec::new();
-    let mut reference = input.remove(0);
-    while !input.is_empty() {
-        for i in 0..input.len() {
-            if let Some((rot, vec)) = has_overlap_rot(&reference, &input[i]) {
-                scanner_pos.push(vec);
-                let transformed: Vec<_> = input
-                    .swap_remove(i)
-                    .iter()
-                    .map(|p| rot.transform_point3d(p.cast()).round().cast() + vec)
-                    .collect();
-                reference.extend_from_slice(&transformed);
-                reference
-                    .sort_unstable_by(|a, b| a.x.cmp(&b.x).then(a.y.cmp(&b.y)).then(a.z.cmp(&b.z)));
-                reference.dedup();
-                break;
-            }
-        }
-    }
-
-    (0..scanner_pos.len())
-        .flat_map(|i| (0..scanner_pos.len()).map(move |j| (i, j)))
-        .map(|(i, j)| {
-            (scanner_pos[i] - scanner_pos[j])
-                .abs()
-                .to_array()
+    let positions: Vec<_> = map
+        .poses
+        .iter()
+        .flatten()
+        .map(|pose| pose.position)
+        .collect();
+    positions
+        .iter()
+        .flat_map(|a| {
+            positions
                 .iter()
-                .sum()
+                .map(move |b| (0..3).map(|i| (a[i] - b[i]).abs()).sum())
         })
         .max()
-        .unwrap()
-}
-
-/// Brute forces all differences between points to see if at least 12 points
-/// overlap. If so, returns the position of `candidate`'s origin in `reference`'s
-/// coordinate system. Does not take rotations into account.
-fn has_overlap(reference: &[Point3D], candidate: &[Point3D]) -> Option<Vector3D> {
-    for can in candidate {
-        for i in 0..reference.len() {
-            let v = *can - reference[i];
-            let overlaps = reference
-                .iter()
-                .filter(|&p| candidate.contains(&(*p + v)))
-                .count();
-            if overlaps >= 12 {
-                return Some(-v);
-            }
-        }
-    }
-    None
-}
-
-/// Brute forces `has_overlap` between the two points, for all 24 possible
-/// rotations and orientations of candidate. If there is an overlap, the return
-/// values are how the candidate should be rotated and where it should be placed
-/// in the reference coordinate system for overlap to occur.
-fn has_overlap_rot(reference: &[Point3D], candidate: &[Point3D]) -> Option<(Rotation3D, Vector3D)> {
-    let (r000, r090, r180, r270) = (
-        Angle::zero(),
-        Angle::frac_pi_2(),
-        Angle::pi(),
-        Angle::frac_pi_2() * 3.0,
-    );
-    let angles = vec![
-        Rotation3D::euler(r000, r000, r000),
-        Rotation3D::euler(r090, r000, r000),
-        Rotation3D::euler(r180, r000, r000),
-        Rotation3D::euler(r270, r000, r000),
-        Rotation3D::euler(r000, r000, r090),
-        Rotation3D::euler(r090, r000, r090),
-        Rotation3D::euler(r180, r000, r090),
-        Rotation3D::euler(r270, r000, r090),
-        Rotation3D::euler(r000, r000, r180),
-        Rotation3D::euler(r090, r000, r180),
-        Rotation3D::euler(r180, r000, r180),
-        Rotation3D::euler(r270, r000, r180),
-        Rotation3D::euler(r000, r000, r270),
-        Rotation3D::euler(r090, r000, r270),
-        Rotation3D::euler(r180, r000, r270),
-        Rotation3D::euler(r270, r000, r270),
-        Rotation3D::euler(r000, r090, r000),
-        Rotation3D::euler(r090, r090, r000),
-        Rotation3D::euler(r180, r090, r000),
-        Rotation3D::euler(r270, r090, r000),
-        Rotation3D::euler(r000, r270, r000),
-        Rotation3D::euler(r090, r270, r000),
-        Rotation3D::euler(r180, r270, r000),
-        Rotation3D::euler(r270, r270, r000),
-    ];
-
-    for a in angles {
-        let rotated: Vec<_> = candidate
-            .iter()
-            .map(|p| a.transform_point3d(p.cast()).round().cast())
-            .collect();
-        if let Some(position) = has_overlap(reference, &rotated) {
-            return Some((a, position));
-        }
-    }
-    None
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
@@ -325,57 +223,147 @@ mod tests {
 -391,539,-444
 553,889,-390";
 
-    #[test]
-    fn rotations() {
-        let (r000, _r090, r180, _r270) = (
-            Angle::<f64>::zero(),
-            Angle::<f64>::frac_pi_2(),
-            Angle::<f64>::pi(),
-            Angle::<f64>::frac_pi_2() * 3.0,
-        );
-        let pts = vec![
-            Point3D::from([0; 3]),
-            Point3D::from([1; 3]),
-            Point3D::from([2; 3]),
-            Point3D::from([3; 3]),
-            Point3D::from([4; 3]),
-            Point3D::from([5; 3]),
-            Point3D::from([6; 3]),
-            Point3D::from([7; 3]),
-            Point3D::from([8; 3]),
-            Point3D::from([9; 3]),
-            Point3D::from([10; 3]),
-            Point3D::from([11; 3]),
-            Point3D::from([12; 3]),
-        ];
-        let pts1: Vec<_> = pts
-            .iter()
-            .map(|p| Point3D::from((p.x, -p.y, -p.z)) + Vector3D::from((1, -1, -1)))
-            .collect();
-        assert_eq!(
-            has_overlap_rot(&pts, &pts1),
-            Some((
-                Rotation3D::euler(r180, r000, r000),
-                Vector3D::from((-1, -1, -1))
-            ))
-        );
-    }
-
     #[test]
     fn sample1() {
-        assert_eq!(part1(&parse_scanners(TEST_INPUT)), 79);
+        assert_eq!(part1(&generator(TEST_INPUT).unwrap()), 79);
     }
     #[test]
     fn sample1a() {
         let input = parse_scanners(SHORTER_TEST);
-        assert!(has_overlap_rot(&input[0], &input[1]).is_some());
+        let map = align_scanners(&input, &AlignConfig::default());
+        assert_eq!(map.links, [(0, 1)]);
     }
     #[test]
     fn sample1b() {
-        assert_eq!(part1(&parse_scanners(SHORTER_TEST)), 12);
+        assert_eq!(part1(&generator(SHORTER_TEST).unwrap()), 12);
     }
     #[test]
     fn sample2() {
-        assert_eq!(part2(&parse_scanners(TEST_INPUT)), 3621);
+        assert_eq!(part2(&generator(TEST_INPUT).unwrap()), 3621);
+    }
+    #[test]
+    fn scanner_map() {
+        let map = generator(TEST_INPUT).unwrap();
+        let positions: Vec<_> = map
+            .poses
+            .iter()
+            .map(|pose| pose.unwrap().position)
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                [0, 0, 0],
+                [68, -1246, -43],
+                [1105, -1205, 1229],
+                [-92, -2380, -20],
+                [-20, -1133, 1061],
+            ]
+        );
+        assert_eq!(map.links, [(0, 1), (1, 3), (1, 4), (4, 2)]);
+        // Every scanner's report lands on the merged beacons
+        let input = parse_scanners(TEST_INPUT);
+        for (points, pose) in input.iter().zip(map.poses.iter().flatten()) {
+            assert!(points
+                .iter()
+                .all(|&p| map.beacons.contains(&pose.transform(p))));
+        }
+    }
+
+    #[test]
+    fn disconnected() {
+        let mut input = parse_scanners(SHORTER_TEST);
+        let stray = vec![[5, 5, 5], [-7, 1, 2]];
+        input.insert(0, stray.clone());
+        input.push(stray);
+
+        let map = align_scanners(&input, &AlignConfig::default());
+        assert_eq!(map.anchor, 0);
+        assert_eq!(map.placed(), [0]);
+        assert_eq!(map.disconnected(), [1, 2, 3]);
+        assert_eq!(map.beacons.len(), 2);
+
+        let largest = AlignConfig {
+            largest_component: true,
+            ..AlignConfig::default()
+        };
+        let map = align_scanners(&input, &largest);
+        assert_eq!(map.anchor, 1);
+        assert_eq!(map.placed(), [1, 2]);
+        assert_eq!(map.disconnected(), [0, 3]);
+        assert_eq!(map.links, [(1, 2)]);
+        assert_eq!(map.beacons.len(), 12);
+        assert_eq!(part1(&map), 12);
+
+        let strict = AlignConfig {
+            min_overlap: 13,
+            ..largest
+        };
+        assert_eq!(align_scanners(&input, &strict).placed(), [0]);
+
+        let mut text = SHORTER_TEST.to_string();
+        text.push_str("\n\n--- scanner 2 ---\n1,2,3\n");
+        assert_eq!(
+            generator(&text),
+            Err(AlignError {
+                disconnected: vec![2]
+            })
+        );
+    }
+
+    #[test]
+    fn noisy() {
+        // Shake every coordinate after scanner 0's by up to 1
+        let mut input = parse_scanners(TEST_INPUT);
+        for (s, points) in input.iter_mut().enumerate().skip(1) {
+            for (i, p) in points.iter_mut().enumerate() {
+                let shake = |n: usize| (n % 3) as i32 - 1;
+                p[0] += shake(s + i);
+                p[1] += shake(s + 2 * i);
+                p[2] += shake(s * i + 1);
+            }
+        }
+        assert_eq!(
+            align_scanners(&input, &AlignConfig::default()).placed(),
+            [0]
+        );
+
+        let config = AlignConfig {
+            tolerance: 4,
+            ..AlignConfig::default()
+        };
+        let map = align_scanners(&input, &config);
+        assert_eq!(map.disconnected(), []);
+        assert_eq!(map.beacons.len(), 79);
+        let exact = generator(TEST_INPUT).unwrap();
+        for (noisy, pose) in map.poses.iter().zip(&exact.poses) {
+            let (noisy, pose) = (noisy.unwrap(), pose.unwrap());
+            assert_eq!(noisy.rotation, pose.rotation);
+            let error: i32 = (0..3)
+                .map(|i| (noisy.position[i] - pose.position[i]).abs())
+                .sum();
+            assert!(error <= 6, "{:?} {:?}", noisy, pose);
+        }
+    }
+
+    #[test]
+    fn export() {
+        let map = generator(SHORTER_TEST).unwrap();
+        let ply = map.to_ply();
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.starts_with("ply\nformat ascii 1.0\n"));
+        assert!(header.contains("element vertex 14\n"));
+        let vertices: Vec<_> = body.lines().collect();
+        assert_eq!(vertices.len(), 14);
+        assert_eq!(vertices[0], "0 0 0 0 255 0 0");
+        assert_eq!(vertices[1], "68 -1246 -43 0 255 0 0");
+        assert_eq!(vertices[2], "-661 -816 -575 1 255 255 255");
+
+        let csv = map.to_csv();
+        let rows: Vec<_> = csv.lines().collect();
+        assert_eq!(rows.len(), 15);
+        assert_eq!(rows[0], "class,id,x,y,z");
+        assert_eq!(rows[2], "scanner,1,68,-1246,-43");
+        assert_eq!(rows[3], "beacon,0,-661,-816,-575");
+        assert_eq!(rows.iter().filter(|r| r.starts_with("beacon,")).count(), 12);
     }
 }
@@ -6,6 +6,8 @@ extern crate aoc_runner;
 extern crate aoc_runner_derive;
 
 pub mod bits;
+pub mod scanner;
+pub mod snailfish;
 
 pub mod day01;
 pub mod day02;
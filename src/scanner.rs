@@ -0,0 +1,675 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// A position or offset in `N` dimensions.
+pub type Point<const N: usize> = [i32; N];
+
+fn add<const N: usize>(a: Point<N>, b: Point<N>) -> Point<N> {
+    std::array::from_fn(|i| a[i] + b[i])
+}
+
+fn sub<const N: usize>(a: Point<N>, b: Point<N>) -> Point<N> {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+
+fn squared_distance<const N: usize>(a: Point<N>, b: Point<N>) -> i64 {
+    (0..N).map(|i| (a[i] as i64 - b[i] as i64).pow(2)).sum()
+}
+
+/// One of the ways a scanner can be turned, as an exact matrix with a single 1
+/// or -1 in every row and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rotation<const N: usize> {
+    rows: [[i32; N]; N],
+}
+
+impl<const N: usize> Rotation<N> {
+    pub const IDENTITY: Self = {
+        let mut rows = [[0; N]; N];
+        let mut i = 0;
+        while i < N {
+            rows[i][i] = 1;
+            i += 1;
+        }
+        Rotation { rows }
+    };
+
+    /// Every orientation, identity first. In three dimensions these are the
+    /// 24 signed permutation matrices with determinant 1. In two, a scanner
+    /// lying flat may also be face down, so mirror images count as well,
+    /// giving all 8 signed permutation matrices.
+    pub fn all() -> Vec<Self> {
+        let mut all = Vec::new();
+        for (perm, odd) in permutations(N) {
+            for signs in 0..1u32 << N {
+                let mut rows = [[0; N]; N];
+                for (i, row) in rows.iter_mut().enumerate() {
+                    row[perm[i]] = if signs & (1 << i) == 0 { 1 } else { -1 };
+                }
+                let determinant = if odd ^ (signs.count_ones() % 2 == 1) {
+                    -1
+                } else {
+                    1
+                };
+                if N == 2 || determinant == 1 {
+                    all.push(Rotation { rows });
+                }
+            }
+        }
+        all
+    }
+
+    pub fn rows(&self) -> [[i32; N]; N] {
+        self.rows
+    }
+
+    /// The rotation that applies `first` and then `self`.
+    pub fn compose(&self, first: &Self) -> Self {
+        let mut rows = [[0; N]; N];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = (0..N).map(|k| self.rows[i][k] * first.rows[k][j]).sum();
+            }
+        }
+        Rotation { rows }
+    }
+
+    /// The transpose, which undoes an orthogonal matrix.
+    pub fn inverse(&self) -> Self {
+        let mut rows = [[0; N]; N];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = self.rows[j][i];
+            }
+        }
+        Rotation { rows }
+    }
+
+    pub fn rotate(&self, p: Point<N>) -> Point<N> {
+        self.rows
+            .map(|row| row.iter().zip(p).map(|(a, b)| a * b).sum())
+    }
+}
+
+/// The permutations of `0..n` in lexicographic order, with whether each is
+/// odd.
+fn permutations(n: usize) -> Vec<(Vec<usize>, bool)> {
+    if n == 0 {
+        return vec![(Vec::new(), false)];
+    }
+    let mut all = Vec::new();
+    for first in 0..n {
+        for (rest, odd) in permutations(n - 1) {
+            let mut perm = vec![first];
+            perm.extend(rest.into_iter().map(|i| if i < first { i } else { i + 1 }));
+            // Moving `first` to the front passes over `first` smaller numbers
+            all.push((perm, odd ^ (first % 2 == 1)));
+        }
+    }
+    all
+}
+
+/// Where a scanner is relative to the anchor scanner: a point `p` it reports
+/// is at `rotation.rotate(p) + position` for the anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pose<const N: usize> {
+    pub rotation: Rotation<N>,
+    pub position: Point<N>,
+}
+
+impl<const N: usize> Pose<N> {
+    pub const IDENTITY: Self = Pose {
+        rotation: Rotation::IDENTITY,
+        position: [0; N],
+    };
+
+    pub fn transform(&self, p: Point<N>) -> Point<N> {
+        add(self.rotation.rotate(p), self.position)
+    }
+
+    /// The pose that applies `first` and then `self`.
+    pub fn compose(&self, first: &Self) -> Self {
+        Pose {
+            rotation: self.rotation.compose(&first.rotation),
+            position: self.transform(first.position),
+        }
+    }
+}
+
+/// Everything known after aligning the scanners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannerMap<const N: usize> {
+    /// The scanner whose coordinates everything is in.
+    pub anchor: usize,
+    /// The pose of every scanner relative to `anchor`, in input order, or
+    /// `None` for scanners that could not be placed.
+    pub poses: Vec<Option<Pose<N>>>,
+    /// Every beacon seen by a placed scanner once, sorted.
+    pub beacons: Vec<Point<N>>,
+    /// `(reference, scanner)` for every scanner placed by overlapping it with
+    /// an already placed reference scanner, in the order they were placed.
+    pub links: Vec<(usize, usize)>,
+}
+
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+impl<const N: usize> ScannerMap<N> {
+    pub fn placed(&self) -> Vec<usize> {
+        (0..self.poses.len())
+            .filter(|&i| self.poses[i].is_some())
+            .collect()
+    }
+
+    /// Scanners that share no chain of overlaps with `anchor`.
+    pub fn disconnected(&self) -> Vec<usize> {
+        (0..self.poses.len())
+            .filter(|&i| self.poses[i].is_none())
+            .collect()
+    }
+
+    /// The placed scanners and then the beacons as an ASCII PLY point cloud,
+    /// with coordinates `x`, `y` and `z` for up to three dimensions. Each
+    /// vertex has a `class` of 0 for a scanner or 1 for a beacon, and a
+    /// colour to match: red for scanners, white for beacons.
+    pub fn to_ply(&self) -> String {
+        assert!(N <= AXES.len(), "PLY has no names for {} axes", N);
+        let scanners = self.scanner_positions();
+        let mut ply = format!(
+            "ply\n\
+             format ascii 1.0\n\
+             comment scanners and beacons relative to scanner {}\n\
+             element vertex {}\n",
+            self.anchor,
+            scanners.len() + self.beacons.len()
+        );
+        for axis in &AXES[..N] {
+            ply += &format!("property int {}\n", axis);
+        }
+        ply += "property uchar class\n\
+                property uchar red\n\
+                property uchar green\n\
+                property uchar blue\n\
+                end_header\n";
+        for (_, p) in scanners {
+            ply += &format!("{} 0 255 0 0\n", join(p, " "));
+        }
+        for &p in &self.beacons {
+            ply += &format!("{} 1 255 255 255\n", join(p, " "));
+        }
+        ply
+    }
+
+    /// The placed scanners and then the beacons as CSV, with a header. The
+    /// class column is `scanner` or `beacon`, and the id is the scanner's
+    /// number or the beacon's index in `beacons`.
+    pub fn to_csv(&self) -> String {
+        assert!(N <= AXES.len(), "CSV has no names for {} axes", N);
+        let mut csv = format!("class,id,{}\n", AXES[..N].join(","));
+        for (i, p) in self.scanner_positions() {
+            csv += &format!("scanner,{},{}\n", i, join(p, ","));
+        }
+        for (i, &p) in self.beacons.iter().enumerate() {
+            csv += &format!("beacon,{},{}\n", i, join(p, ","));
+        }
+        csv
+    }
+
+    fn scanner_positions(&self) -> Vec<(usize, Point<N>)> {
+        self.poses
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pose)| Some((i, pose.as_ref()?.position)))
+            .collect()
+    }
+}
+
+fn join<const N: usize>(p: Point<N>, separator: &str) -> String {
+    p.map(|c| c.to_string()).join(separator)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignConfig {
+    /// Anchor the map at the largest group of scanners that overlap each
+    /// other, rather than at scanner 0. Ties go to the group with the lowest
+    /// numbered scanner, which becomes the anchor.
+    pub largest_component: bool,
+    /// How many beacons two scanners have to share to be aligned. At least 2.
+    pub min_overlap: usize,
+    /// How far apart two reports of the same beacon may be, for noisy
+    /// scanners. Zero requires exact matches.
+    pub tolerance: u32,
+}
+
+impl Default for AlignConfig {
+    fn default() -> Self {
+        Self {
+            largest_component: false,
+            min_overlap: 12,
+            tolerance: 0,
+        }
+    }
+}
+
+/// Scanners that could not be placed, when all of them have to be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignError {
+    pub disconnected: Vec<usize>,
+}
+
+impl fmt::Display for AlignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "scanners {:?} can not be placed", self.disconnected)
+    }
+}
+
+impl std::error::Error for AlignError {}
+
+/// Places as many scanners as possible relative to scanner 0, or to the
+/// largest group if `config` says so. Scanners are aligned against each
+/// other, starting from the anchor, so each is placed by a single scanner it
+/// shares at least `config.min_overlap` beacons with. Beacons reported within
+/// `config.tolerance` of each other are merged.
+pub fn align_scanners<const N: usize>(
+    scanners: &[Vec<Point<N>>],
+    config: &AlignConfig,
+) -> ScannerMap<N> {
+    assert!(config.min_overlap >= 2, "overlaps are found between pairs");
+    let fingerprints: Vec<_> = scanners
+        .iter()
+        .map(|points| Fingerprint::new(points.clone(), config.tolerance))
+        .collect();
+    let rotations = Rotation::all();
+    let mut grouped = vec![false; scanners.len()];
+    let mut best: Option<ScannerMap<N>> = None;
+    for root in 0..scanners.len() {
+        if grouped[root] {
+            continue;
+        }
+        if root > 0 && !config.largest_component {
+            break;
+        }
+        let group = place_group(root, &fingerprints, &rotations, &grouped, config);
+        for (i, pose) in group.poses.iter().enumerate() {
+            grouped[i] |= pose.is_some();
+        }
+        // A group has one more scanner than links
+        if best
+            .as_ref()
+            .is_none_or(|b| group.links.len() > b.links.len())
+        {
+            best = Some(group);
+        }
+    }
+    let mut map = best.unwrap_or(ScannerMap {
+        anchor: 0,
+        poses: Vec::new(),
+        beacons: Vec::new(),
+        links: Vec::new(),
+    });
+
+    let mut beacons = Grid::new(config.tolerance);
+    for (points, pose) in scanners.iter().zip(&map.poses) {
+        if let Some(pose) = pose {
+            for &p in points {
+                let p = pose.transform(p);
+                if beacons.near(p).is_none() {
+                    beacons.insert(p);
+                }
+            }
+        }
+    }
+    map.beacons = beacons.cells.into_values().flatten().collect();
+    map.beacons.sort_unstable();
+    map
+}
+
+/// Places every scanner connected to `root` through overlaps, relative to
+/// `root`, leaving out the beacons. Scanners marked in `skip` are known to be
+/// elsewhere. Only `rotations` are tried.
+fn place_group<const N: usize>(
+    root: usize,
+    fingerprints: &[Fingerprint<N>],
+    rotations: &[Rotation<N>],
+    skip: &[bool],
+    config: &AlignConfig,
+) -> ScannerMap<N> {
+    let mut poses = vec![None; fingerprints.len()];
+    let mut links = Vec::new();
+    poses[root] = Some(Pose::IDENTITY);
+    let mut queue = VecDeque::from([root]);
+    while let Some(reference) = queue.pop_front() {
+        let placed = poses[reference].unwrap();
+        for scanner in 0..fingerprints.len() {
+            if poses[scanner].is_some() || skip[scanner] {
+                continue;
+            }
+            if let Some(pose) = find_alignment(
+                &fingerprints[reference],
+                &fingerprints[scanner],
+                rotations,
+                config,
+            ) {
+                poses[scanner] = Some(placed.compose(&pose));
+                links.push((reference, scanner));
+                queue.push_back(scanner);
+            }
+        }
+    }
+    ScannerMap {
+        anchor: root,
+        poses,
+        beacons: Vec::new(),
+        links,
+    }
+}
+
+/// A set of points indexed by the squared distances between them, which do not
+/// change under rotation and translation.
+struct Fingerprint<const N: usize> {
+    points: Vec<Point<N>>,
+    grid: Grid<N>,
+    /// Every two points with the squared distance between them, sorted.
+    pairs: Vec<(i64, usize, usize)>,
+}
+
+impl<const N: usize> Fingerprint<N> {
+    fn new(points: Vec<Point<N>>, tolerance: u32) -> Self {
+        let mut pairs = Vec::new();
+        for i in 0..points.len() {
+            for j in i + 1..points.len() {
+                pairs.push((squared_distance(points[i], points[j]), i, j));
+            }
+        }
+        pairs.sort_unstable();
+        let mut grid = Grid::new(tolerance);
+        for &p in &points {
+            grid.insert(p);
+        }
+        Self {
+            points,
+            grid,
+            pairs,
+        }
+    }
+
+    /// The pairs of points whose distance could be `d` with both ends off by
+    /// up to the tolerance.
+    fn pairs_near(&self, d: i64) -> &[(i64, usize, usize)] {
+        let (low, high) = match self.grid.tolerance {
+            0 => (d, d),
+            t => {
+                let (d, slack) = ((d as f64).sqrt(), 2.0 * t as f64);
+                let low = (d - slack).max(0.0);
+                (
+                    (low * low).floor() as i64,
+                    ((d + slack) * (d + slack)).ceil() as i64,
+                )
+            }
+        };
+        let start = self.pairs.partition_point(|&(e, _, _)| e < low);
+        let end = self.pairs.partition_point(|&(e, _, _)| e <= high);
+        &self.pairs[start..end]
+    }
+}
+
+/// Points bucketed in cubes as wide as the tolerance, so that the points
+/// within the tolerance of another are in the 3^N cubes around it.
+struct Grid<const N: usize> {
+    tolerance: u32,
+    cells: HashMap<Point<N>, Vec<Point<N>>>,
+}
+
+impl<const N: usize> Grid<N> {
+    fn new(tolerance: u32) -> Self {
+        Self {
+            tolerance,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, p: Point<N>) -> Point<N> {
+        let size = self.tolerance.max(1) as i32;
+        p.map(|c| c.div_euclid(size))
+    }
+
+    fn insert(&mut self, p: Point<N>) {
+        self.cells.entry(self.cell(p)).or_default().push(p);
+    }
+
+    /// The nearest point within the tolerance of `p`.
+    fn near(&self, p: Point<N>) -> Option<Point<N>> {
+        self.within(p).min_by_key(|&q| squared_distance(p, q))
+    }
+
+    /// Every point within the tolerance of `p`.
+    fn within(&self, p: Point<N>) -> impl Iterator<Item = Point<N>> + '_ {
+        let max = (self.tolerance as i64).pow(2);
+        let cell = self.cell(p);
+        let reach: i32 = if self.tolerance == 0 { 0 } else { 1 };
+        let width = 2 * reach + 1;
+        (0..width.pow(N as u32))
+            .map(move |n| {
+                // The digits of `n` in base `width` give the offset on each axis
+                add(
+                    cell,
+                    std::array::from_fn(|i| n / width.pow(i as u32) % width - reach),
+                )
+            })
+            .flat_map(|neighbour| self.cells.get(&neighbour).into_iter().flatten())
+            .copied()
+            .filter(move |&q| squared_distance(p, q) <= max)
+    }
+}
+
+/// Finds how `candidate` should be turned and placed in the reference
+/// coordinate system for at least `config.min_overlap` points to overlap.
+///
+/// With the default of 12, shared points give 66 shared distances, and every
+/// one of them is at the same distance from 11 others in both sets, so the
+/// pairs of points that could correspond are found from the distances alone.
+/// Only those are tried with each of `rotations`. With a tolerance, the
+/// distances only have to be close and the placement is averaged over the
+/// overlap. Each point is matched with at most one other, nearest first, so a
+/// cluster of points around one beacon only counts once.
+fn find_alignment<const N: usize>(
+    reference: &Fingerprint<N>,
+    candidate: &Fingerprint<N>,
+    rotations: &[Rotation<N>],
+    config: &AlignConfig,
+) -> Option<Pose<N>> {
+    let min = config.min_overlap;
+    let shared = candidate
+        .pairs
+        .iter()
+        .filter(|&&(d, _, _)| !reference.pairs_near(d).is_empty())
+        .count();
+    if shared < min * (min - 1) / 2 {
+        return None;
+    }
+
+    // Each point pair that could correspond, with a partner of the reference
+    // point that is at the right distance from it
+    let mut partners = Vec::new();
+    for &(d, k, l) in &candidate.pairs {
+        for &(_, i, j) in reference.pairs_near(d) {
+            partners.extend([(i, k, j), (i, l, j), (j, k, i), (j, l, i)]);
+        }
+    }
+    partners.sort_unstable();
+    partners.dedup();
+    let mut votes: HashMap<(usize, usize), usize> = HashMap::new();
+    for (i, k, _) in partners {
+        *votes.entry((i, k)).or_default() += 1;
+    }
+    let mut likely: Vec<_> = votes.into_iter().filter(|&(_, n)| n >= min - 1).collect();
+    likely.sort_unstable_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+
+    for ((i, k), _) in likely {
+        for &rotation in rotations {
+            let position = sub(reference.points[i], rotation.rotate(candidate.points[k]));
+            let mut matches: Vec<(i64, usize, Point<N>)> = Vec::new();
+            for (m, &p) in candidate.points.iter().enumerate() {
+                let p = add(rotation.rotate(p), position);
+                let near = reference.grid.within(p);
+                matches.extend(near.map(|q| (squared_distance(p, q), m, q)));
+            }
+            matches.sort_unstable();
+            let mut matched = vec![false; candidate.points.len()];
+            let mut claimed = HashSet::new();
+            let mut offsets = Vec::new();
+            for (_, m, q) in matches {
+                if !matched[m] && claimed.insert(q) {
+                    matched[m] = true;
+                    offsets.push(sub(q, rotation.rotate(candidate.points[m])));
+                }
+            }
+            if offsets.len() >= min {
+                let n = offsets.len() as i32;
+                let position = std::array::from_fn(|axis| {
+                    let sum: i32 = offsets.iter().map(|o| o[axis]).sum();
+                    (2 * sum + n).div_euclid(2 * n)
+                });
+                return Some(Pose { rotation, position });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn group<const N: usize>(all: &[Rotation<N>]) {
+        let set: HashSet<_> = all.iter().copied().collect();
+        assert_eq!(set.len(), all.len());
+        assert_eq!(all[0], Rotation::IDENTITY);
+        for a in all {
+            assert_eq!(a.compose(&a.inverse()), Rotation::IDENTITY);
+            assert_eq!(a.inverse().compose(a), Rotation::IDENTITY);
+            for b in all {
+                assert!(set.contains(&a.compose(b)));
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_group() {
+        let all = Rotation::<3>::all();
+        assert_eq!(all.len(), 24);
+        group(&all);
+        // Every orientation of an asymmetric vector is reached exactly once
+        let images: HashSet<_> = all.iter().map(|r| r.rotate([1, 2, 3])).collect();
+        assert_eq!(images.len(), 24);
+        // A quarter turn about z, four times over, is the identity
+        let quarter = all
+            .iter()
+            .find(|r| r.rotate([1, 0, 0]) == [0, 1, 0] && r.rotate([0, 0, 1]) == [0, 0, 1])
+            .unwrap();
+        let turned = (0..4).fold(Rotation::IDENTITY, |r, _| quarter.compose(&r));
+        assert_eq!(turned, Rotation::IDENTITY);
+
+        let flat = Rotation::<2>::all();
+        assert_eq!(flat.len(), 8);
+        group(&flat);
+        let images: HashSet<_> = flat.iter().map(|r| r.rotate([1, 2])).collect();
+        assert_eq!(images.len(), 8);
+    }
+
+    #[test]
+    fn rotations() {
+        let pts: Vec<Point<3>> = (0..13).map(|i| [i; 3]).collect();
+        let pts1: Vec<_> = pts
+            .iter()
+            .map(|p| [p[0] + 1, -p[1] - 1, -p[2] - 1])
+            .collect();
+        let pose = find_alignment(
+            &Fingerprint::new(pts, 0),
+            &Fingerprint::new(pts1, 0),
+            &Rotation::all(),
+            &AlignConfig::default(),
+        );
+        assert_eq!(
+            pose,
+            Some(Pose {
+                rotation: Rotation {
+                    rows: [[1, 0, 0], [0, -1, 0], [0, 0, -1]]
+                },
+                position: [-1, -1, -1]
+            })
+        );
+    }
+
+    #[test]
+    fn cluster() {
+        let config = AlignConfig {
+            min_overlap: 6,
+            tolerance: 2,
+            ..AlignConfig::default()
+        };
+        // The first beacon is as far from the second as the last, which the
+        // candidate does not see, so the second gets enough votes
+        let mut reference: Vec<Point<3>> = vec![
+            [100, 0, 0],
+            [0, 0, 0],
+            [300, -120, 40],
+            [-150, 260, -310],
+            [420, 380, -90],
+            [0, 100, 0],
+        ];
+        let shift = |p: &Point<3>| sub(*p, [7, -3, 5]);
+        let mut candidate: Vec<_> = reference[..5].iter().map(shift).collect();
+        // Two more points around the first beacon, which may only be matched
+        // once, so five points overlap
+        candidate.push(add(candidate[0], [1, 1, 0]));
+        candidate.push(add(candidate[0], [-1, 0, 1]));
+        let align = |reference: &[Point<3>], candidate: &[Point<3>]| {
+            find_alignment(
+                &Fingerprint::new(reference.to_vec(), config.tolerance),
+                &Fingerprint::new(candidate.to_vec(), config.tolerance),
+                &Rotation::all(),
+                &config,
+            )
+        };
+        assert_eq!(align(&reference, &candidate), None);
+
+        // A sixth beacon seen by both does make the overlap
+        reference.push([-60, -340, 200]);
+        candidate.push(shift(&reference[6]));
+        assert_eq!(
+            align(&reference, &candidate),
+            Some(Pose {
+                rotation: Rotation::IDENTITY,
+                position: [7, -3, 5]
+            })
+        );
+    }
+
+    #[test]
+    fn flat() {
+        let beacons: Vec<Point<2>> = vec![[0, 2], [4, 1], [3, 3], [-2, 5], [7, -3], [1, -6]];
+        // Scanner 1 sits at (5, 1), sees the world mirrored in its x axis and
+        // misses the first beacon
+        let scanner1 = beacons[1..].iter().map(|&[x, y]| [x - 5, 1 - y]).collect();
+        // Scanner 2 sits at (-3, 0), turned a quarter, and only overlaps
+        // scanner 1
+        let scanner2 = beacons[2..].iter().map(|&[x, y]| [y, -(x + 3)]).collect();
+        let config = AlignConfig {
+            min_overlap: 4,
+            ..AlignConfig::default()
+        };
+        let map = align_scanners(&[beacons[..5].to_vec(), scanner1, scanner2], &config);
+        assert_eq!(map.disconnected(), []);
+        assert_eq!(map.links, [(0, 1), (1, 2)]);
+        let positions: Vec<_> = map.poses.iter().map(|p| p.unwrap().position).collect();
+        assert_eq!(positions, [[0, 0], [5, 1], [-3, 0]]);
+        let mut expected = beacons.clone();
+        expected.sort_unstable();
+        assert_eq!(map.beacons, expected);
+        assert_eq!(
+            map.to_csv().lines().take(3).collect::<Vec<_>>(),
+            ["class,id,x,y", "scanner,0,0,0", "scanner,1,5,1"]
+        );
+    }
+}
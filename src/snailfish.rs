@@ -0,0 +1,739 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+use std::str::FromStr;
+
+/// A snailfish number: either a regular number or a pair of snailfish
+/// numbers. Adding with `+` reduces the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnailNumber {
+    Regular(u64),
+    Pair(Box<SnailNumber>, Box<SnailNumber>),
+}
+
+/// The parameters of snailfish arithmetic. The default is the puzzle's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnailRules {
+    /// Pairs nested inside this many pairs explode.
+    pub explode_depth: usize,
+    /// Regular numbers above this split. Reducing panics if this is 0, since
+    /// then even a 1 would split.
+    pub split_threshold: u64,
+    /// Magnitude weight of the left element of a pair.
+    pub left_weight: u64,
+    /// Magnitude weight of the right element of a pair.
+    pub right_weight: u64,
+}
+
+impl Default for SnailRules {
+    fn default() -> Self {
+        Self {
+            explode_depth: 4,
+            split_threshold: 9,
+            left_weight: 3,
+            right_weight: 2,
+        }
+    }
+}
+
+/// Like `SnailNumber::magnitude_with`, the bounds report an error rather than
+/// a magnitude that does not fit in a `u64`.
+impl SnailRules {
+    /// No reduced number has a larger magnitude: it is nested at most
+    /// `explode_depth` deep, with regular numbers of at most
+    /// `split_threshold`.
+    pub fn max_magnitude(&self) -> Result<u64, SnailOverflow> {
+        let weight = self.left_weight.checked_add(self.right_weight);
+        (0..self.explode_depth)
+            .try_fold(self.split_threshold, |m, _| m.checked_mul(weight?))
+            .ok_or(SnailOverflow)
+    }
+
+    /// No reduced number whose regular numbers add up to at most `total` has a
+    /// larger magnitude. Reducing never adds to the total, so this also
+    /// bounds the sum of two numbers with that total between them.
+    ///
+    /// Every regular number sits above a distinct position at depth
+    /// `explode_depth`, which is at least as heavy if it is reached by always
+    /// turning towards the heavier side. So the bound fills the heaviest
+    /// positions with `split_threshold` first.
+    pub fn magnitude_bound(&self, total: u64) -> Result<u64, SnailOverflow> {
+        if self.left_weight == 0 && self.right_weight == 0 {
+            // Only a number that is not a pair has a magnitude
+            return Ok(total.min(self.split_threshold));
+        }
+        let depth = u32::try_from(self.explode_depth).map_err(|_| SnailOverflow)?;
+        // Weights of the positions with some number of left turns, or `None`
+        // if too heavy for a `u64`, with how many such positions there are
+        let mut positions: Vec<(Option<u64>, u64)> = (0..=depth)
+            .map(|left| {
+                let weight = self
+                    .left_weight
+                    .checked_pow(left)
+                    .and_then(|w| w.checked_mul(self.right_weight.checked_pow(depth - left)?));
+                (weight, saturating_binomial(depth as u64, left as u64))
+            })
+            .collect();
+        positions
+            .sort_unstable_by_key(|&(weight, _)| std::cmp::Reverse((weight.is_none(), weight)));
+
+        let mut rest = total;
+        let mut bound: u64 = 0;
+        for (weight, count) in positions {
+            let value = rest.min(count.saturating_mul(self.split_threshold));
+            if value > 0 {
+                let weighted = weight.and_then(|w| w.checked_mul(value));
+                bound = weighted
+                    .and_then(|weighted| bound.checked_add(weighted))
+                    .ok_or(SnailOverflow)?;
+                rest -= value;
+            }
+        }
+        Ok(bound)
+    }
+}
+
+fn saturating_binomial(n: u64, k: u64) -> u64 {
+    let k = k.min(n - k);
+    let mut c: u128 = 1;
+    for j in 1..=k {
+        // Exact, and growing with `j`
+        c = c * (n - k + j) as u128 / j as u128;
+        if c > u64::MAX as u128 {
+            return u64::MAX;
+        }
+    }
+    c as u64
+}
+
+impl SnailNumber {
+    /// Pairs two numbers without reducing, unlike `+`.
+    pub fn pair(left: SnailNumber, right: SnailNumber) -> Self {
+        SnailNumber::Pair(Box::new(left), Box::new(right))
+    }
+
+    /// Explodes and splits until neither applies. If an explode pushes a
+    /// regular number past `u64::MAX`, the number is left partly reduced.
+    pub fn reduce(&mut self) -> Result<(), SnailOverflow> {
+        self.reduce_with(&SnailRules::default())
+    }
+
+    pub fn reduce_with(&mut self, rules: &SnailRules) -> Result<(), SnailOverflow> {
+        while self.reduce_step(rules)?.is_some() {}
+        Ok(())
+    }
+
+    /// Pairs and reduces under `rules`, like `+` does under the default ones.
+    pub fn add_with(self, other: SnailNumber, rules: &SnailRules) -> Result<Self, SnailOverflow> {
+        let mut sum = Self::pair(self, other);
+        sum.reduce_with(rules)?;
+        Ok(sum)
+    }
+
+    /// Reduces a copy of this number, returning every explode and split in
+    /// order together with the number as it was after it.
+    pub fn reduction(&self) -> Result<Vec<ReduceStep>, SnailOverflow> {
+        self.reduction_with(&SnailRules::default())
+    }
+
+    pub fn reduction_with(&self, rules: &SnailRules) -> Result<Vec<ReduceStep>, SnailOverflow> {
+        let mut num = self.clone();
+        let mut steps = Vec::new();
+        while let Some(action) = num.reduce_step(rules)? {
+            steps.push(ReduceStep {
+                action,
+                after: num.clone(),
+            });
+        }
+        Ok(steps)
+    }
+
+    /// Applies a single explode or split, returning `None` if the number was
+    /// already reduced.
+    fn reduce_step(&mut self, rules: &SnailRules) -> Result<Option<ReduceAction>, SnailOverflow> {
+        assert!(
+            rules.split_threshold > 0,
+            "a split threshold of 0 splits every 1"
+        );
+        let mut path = Vec::new();
+        if let Some((action, _, _)) = self.reduce_nested(&mut path, rules.explode_depth)? {
+            return Ok(Some(action));
+        }
+        Ok(self.reduce_split(&mut path, rules.split_threshold))
+    }
+
+    /// Explodes the leftmost pair nested inside `depth` pairs, returning the
+    /// values that still have to be added to the regular numbers to its left
+    /// and right. `path` leads to `self` and is left leading to the pair.
+    fn reduce_nested(
+        &mut self,
+        path: &mut Vec<usize>,
+        depth: usize,
+    ) -> Result<Option<(ReduceAction, u64, u64)>, SnailOverflow> {
+        let (left, right) = match self {
+            SnailNumber::Regular(_) => return Ok(None),
+            SnailNumber::Pair(left, right) => (left, right),
+        };
+        if path.len() >= depth {
+            if let (SnailNumber::Regular(l), SnailNumber::Regular(r)) = (&**left, &**right) {
+                let (l, r) = (*l, *r);
+                *self = SnailNumber::Regular(0);
+                let action = ReduceAction::Explode {
+                    path: path.clone(),
+                    left: l,
+                    right: r,
+                };
+                return Ok(Some((action, l, r)));
+            }
+        }
+        path.push(0);
+        if let Some((action, l, r)) = left.reduce_nested(path, depth)? {
+            right.add_leftmost(r)?;
+            return Ok(Some((action, l, 0)));
+        }
+        *path.last_mut().unwrap() = 1;
+        if let Some((action, l, r)) = right.reduce_nested(path, depth)? {
+            left.add_rightmost(l)?;
+            return Ok(Some((action, 0, r)));
+        }
+        path.pop();
+        Ok(None)
+    }
+
+    /// Splits the leftmost regular number above `threshold`.
+    fn reduce_split(&mut self, path: &mut Vec<usize>, threshold: u64) -> Option<ReduceAction> {
+        match self {
+            SnailNumber::Regular(v) if *v > threshold => {
+                let v = *v;
+                *self = Self::pair(
+                    SnailNumber::Regular(v / 2),
+                    SnailNumber::Regular(v.div_ceil(2)),
+                );
+                Some(ReduceAction::Split {
+                    path: path.clone(),
+                    value: v,
+                })
+            }
+            SnailNumber::Regular(_) => None,
+            SnailNumber::Pair(left, right) => {
+                path.push(0);
+                if let Some(action) = left.reduce_split(path, threshold) {
+                    return Some(action);
+                }
+                *path.last_mut().unwrap() = 1;
+                let action = right.reduce_split(path, threshold);
+                path.pop();
+                action
+            }
+        }
+    }
+
+    fn add_leftmost(&mut self, value: u64) -> Result<(), SnailOverflow> {
+        match self {
+            SnailNumber::Regular(v) => {
+                *v = v.checked_add(value).ok_or(SnailOverflow)?;
+                Ok(())
+            }
+            SnailNumber::Pair(left, _) => left.add_leftmost(value),
+        }
+    }
+
+    fn add_rightmost(&mut self, value: u64) -> Result<(), SnailOverflow> {
+        match self {
+            SnailNumber::Regular(v) => {
+                *v = v.checked_add(value).ok_or(SnailOverflow)?;
+                Ok(())
+            }
+            SnailNumber::Pair(_, right) => right.add_rightmost(value),
+        }
+    }
+
+    pub fn magnitude(&self) -> Result<u64, SnailOverflow> {
+        self.magnitude_with(&SnailRules::default())
+    }
+
+    pub fn magnitude_with(&self, rules: &SnailRules) -> Result<u64, SnailOverflow> {
+        match self {
+            SnailNumber::Regular(v) => Ok(*v),
+            SnailNumber::Pair(left, right) => {
+                let left = rules.left_weight.checked_mul(left.magnitude_with(rules)?);
+                let right = rules.right_weight.checked_mul(right.magnitude_with(rules)?);
+                left.zip(right)
+                    .and_then(|(left, right)| left.checked_add(right))
+                    .ok_or(SnailOverflow)
+            }
+        }
+    }
+}
+
+/// A regular number or magnitude that does not fit in a `u64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnailOverflow;
+
+impl fmt::Display for SnailOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "snailfish number overflowed")
+    }
+}
+
+impl std::error::Error for SnailOverflow {}
+
+/// What a single reduction step did. Paths lead from the outermost pair, with
+/// 0 for a left element and 1 for a right one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReduceAction {
+    /// The pair `[left,right]` at `path` exploded.
+    Explode {
+        path: Vec<usize>,
+        left: u64,
+        right: u64,
+    },
+    /// The regular number `value` at `path` split.
+    Split { path: Vec<usize>, value: u64 },
+}
+
+/// One entry of `SnailNumber::reduction`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReduceStep {
+    pub action: ReduceAction,
+    /// The whole number after the action.
+    pub after: SnailNumber,
+}
+
+impl fmt::Display for ReduceAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self {
+            ReduceAction::Explode { path, left, right } => {
+                write!(f, "explode [{},{}] at root", left, right)?;
+                path
+            }
+            ReduceAction::Split { path, value } => {
+                write!(f, "split {} at root", value)?;
+                path
+            }
+        };
+        for i in path {
+            write!(f, ".{}", i)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ReduceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.action, self.after)
+    }
+}
+
+/// Panics if a regular number overflows, like `+` on integers does in debug
+/// builds. `add_with` reports it instead.
+impl Add for SnailNumber {
+    type Output = SnailNumber;
+
+    fn add(self, other: SnailNumber) -> SnailNumber {
+        self.add_with(other, &SnailRules::default())
+            .expect("snailfish addition overflowed")
+    }
+}
+
+impl Add<&SnailNumber> for &SnailNumber {
+    type Output = SnailNumber;
+
+    fn add(self, other: &SnailNumber) -> SnailNumber {
+        self.clone() + other.clone()
+    }
+}
+
+impl AddAssign for SnailNumber {
+    fn add_assign(&mut self, other: SnailNumber) {
+        let left = std::mem::replace(self, SnailNumber::Regular(0));
+        *self = left + other;
+    }
+}
+
+impl AddAssign<&SnailNumber> for SnailNumber {
+    fn add_assign(&mut self, other: &SnailNumber) {
+        *self += other.clone();
+    }
+}
+
+/// Adds up a list of numbers from left to right. Snailfish numbers have no
+/// zero, so this panics if there are none. Like `+`, it also panics on
+/// overflow.
+impl Sum for SnailNumber {
+    fn sum<I: Iterator<Item = SnailNumber>>(mut iter: I) -> Self {
+        let first = iter.next().expect("sum of no snailfish numbers");
+        iter.fold(first, |sum, num| sum + num)
+    }
+}
+
+impl<'a> Sum<&'a SnailNumber> for SnailNumber {
+    fn sum<I: Iterator<Item = &'a SnailNumber>>(iter: I) -> Self {
+        iter.cloned().sum()
+    }
+}
+
+impl fmt::Display for SnailNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnailNumber::Regular(v) => write!(f, "{}", v),
+            SnailNumber::Pair(left, right) => write!(f, "[{},{}]", left, right),
+        }
+    }
+}
+
+/// Where and why a snailfish number failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSnailError {
+    /// Counting from 1.
+    pub line: usize,
+    /// In characters, counting from 1.
+    pub column: usize,
+    pub kind: ParseSnailErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseSnailErrorKind {
+    /// A line with no number on it.
+    Empty,
+    /// A `[` that is never closed. Reported at the bracket.
+    UnclosedBracket,
+    /// A `]` that closes nothing.
+    UnmatchedBracket,
+    /// Two elements of a pair with no comma between them.
+    MissingComma,
+    /// A pair with fewer or more than two elements. Reported at the `]` or
+    /// `,` that shows it.
+    WrongArity,
+    /// A regular number of more than one digit, in `parse_homework`.
+    MultiDigit,
+    /// A regular number that does not fit in a `u64`.
+    TooLarge,
+    Unexpected(char),
+}
+
+impl fmt::Display for ParseSnailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.kind {
+            ParseSnailErrorKind::Empty => write!(f, "no snailfish number"),
+            ParseSnailErrorKind::UnclosedBracket => write!(f, "unclosed bracket"),
+            ParseSnailErrorKind::UnmatchedBracket => write!(f, "unmatched closing bracket"),
+            ParseSnailErrorKind::MissingComma => write!(f, "missing comma"),
+            ParseSnailErrorKind::WrongArity => write!(f, "pair without exactly two elements"),
+            ParseSnailErrorKind::MultiDigit => write!(f, "regular number of more than one digit"),
+            ParseSnailErrorKind::TooLarge => write!(f, "regular number too large"),
+            ParseSnailErrorKind::Unexpected(ch) => write!(f, "unexpected {:?}", ch),
+        }
+    }
+}
+
+impl std::error::Error for ParseSnailError {}
+
+/// Parses any number of digits per regular number, as `Display` writes them.
+impl FromStr for SnailNumber {
+    type Err = ParseSnailError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s, 1, false).number_line()
+    }
+}
+
+impl SnailNumber {
+    /// Parses a list of numbers, one per line, with the single digit regular
+    /// numbers of the puzzle input.
+    pub fn parse_homework(input: &str) -> Result<Vec<SnailNumber>, ParseSnailError> {
+        input
+            .lines()
+            .enumerate()
+            .map(|(i, line)| Parser::new(line, i + 1, true).number_line())
+            .collect()
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    line: usize,
+    single_digits: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, line: usize, single_digits: bool) -> Self {
+        Self {
+            input,
+            pos: 0,
+            line,
+            single_digits,
+        }
+    }
+
+    fn error(&self, pos: usize, kind: ParseSnailErrorKind) -> ParseSnailError {
+        ParseSnailError {
+            line: self.line,
+            column: self.input[..pos].chars().count() + 1,
+            kind,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    /// Reports whatever is at the current position as out of place.
+    fn unexpected(&self) -> ParseSnailError {
+        let kind = match self.input[self.pos..].chars().next() {
+            None => ParseSnailErrorKind::Empty,
+            Some(']') => ParseSnailErrorKind::UnmatchedBracket,
+            Some(ch) => ParseSnailErrorKind::Unexpected(ch),
+        };
+        self.error(self.pos, kind)
+    }
+
+    fn number_line(mut self) -> Result<SnailNumber, ParseSnailError> {
+        let number = self.number(None)?;
+        match self.peek() {
+            None => Ok(number),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    /// Parses the number at the current position. `open` is the position of
+    /// the bracket it is in, if any.
+    fn number(&mut self, open: Option<usize>) -> Result<SnailNumber, ParseSnailError> {
+        match self.peek() {
+            Some(b'[') => self.pair(),
+            Some(ch) if ch.is_ascii_digit() => self.regular(),
+            None if open.is_some() => {
+                Err(self.error(open.unwrap(), ParseSnailErrorKind::UnclosedBracket))
+            }
+            Some(b']' | b',') if open.is_some() => {
+                Err(self.error(self.pos, ParseSnailErrorKind::WrongArity))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn pair(&mut self) -> Result<SnailNumber, ParseSnailError> {
+        let open = self.pos;
+        self.pos += 1;
+        let left = self.number(Some(open))?;
+        match self.peek() {
+            Some(b',') => self.pos += 1,
+            Some(b']') => return Err(self.error(self.pos, ParseSnailErrorKind::WrongArity)),
+            Some(b'[' | b'0'..=b'9') => {
+                return Err(self.error(self.pos, ParseSnailErrorKind::MissingComma))
+            }
+            None => return Err(self.error(open, ParseSnailErrorKind::UnclosedBracket)),
+            Some(_) => return Err(self.unexpected()),
+        }
+        let right = self.number(Some(open))?;
+        match self.peek() {
+            Some(b']') => self.pos += 1,
+            Some(b',') => return Err(self.error(self.pos, ParseSnailErrorKind::WrongArity)),
+            Some(b'[' | b'0'..=b'9') => {
+                return Err(self.error(self.pos, ParseSnailErrorKind::MissingComma))
+            }
+            None => return Err(self.error(open, ParseSnailErrorKind::UnclosedBracket)),
+            Some(_) => return Err(self.unexpected()),
+        }
+        Ok(SnailNumber::pair(left, right))
+    }
+
+    fn regular(&mut self) -> Result<SnailNumber, ParseSnailError> {
+        let start = self.pos;
+        let digits = self.input[start..]
+            .bytes()
+            .take_while(|ch| ch.is_ascii_digit())
+            .count();
+        if self.single_digits && digits > 1 {
+            return Err(self.error(start, ParseSnailErrorKind::MultiDigit));
+        }
+        self.pos += digits;
+        self.input[start..self.pos]
+            .parse()
+            .map(SnailNumber::Regular)
+            .map_err(|_| self.error(start, ParseSnailErrorKind::TooLarge))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snail(s: &str) -> SnailNumber {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in [
+            "[1,2]",
+            "[[[[1,3],[5,3]],[[1,3],[8,7]]],[[[4,9],[6,9]],[[8,2],[7,3]]]]",
+            "[300,[91,7]]",
+        ] {
+            assert_eq!(snail(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |s: &str| {
+            let e = s.parse::<SnailNumber>().unwrap_err();
+            (e.column, e.kind)
+        };
+        use ParseSnailErrorKind::*;
+        assert_eq!(error("[1,2"), (1, UnclosedBracket));
+        assert_eq!(error("[[1,2],[3,"), (8, UnclosedBracket));
+        assert_eq!(error("[1,2]]"), (6, UnmatchedBracket));
+        assert_eq!(error("]"), (1, UnmatchedBracket));
+        assert_eq!(error("[1[2,3]]"), (3, MissingComma));
+        assert_eq!(error("[1]"), (3, WrongArity));
+        assert_eq!(error("[]"), (2, WrongArity));
+        assert_eq!(error("[1,2,3]"), (5, WrongArity));
+        assert_eq!(error("[1,]"), (4, WrongArity));
+        assert_eq!(error("[1;2]"), (3, Unexpected(';')));
+        assert_eq!(error("[x,2]"), (2, Unexpected('x')));
+        assert_eq!(error(""), (1, Empty));
+        assert_eq!(error("[1,99999999999999999999]"), (4, TooLarge));
+
+        let e = SnailNumber::parse_homework("[1,2]\n[[3,4],5]\n[[6,7],[8,10]]").unwrap_err();
+        assert_eq!((e.line, e.column, e.kind), (3, 11, MultiDigit));
+        assert_eq!(
+            e.to_string(),
+            "line 3, column 11: regular number of more than one digit"
+        );
+    }
+
+    #[test]
+    fn reduce() {
+        let num = snail("[[[[4,3],4],4],[7,[[8,4],9]]]") + snail("[1,1]");
+        assert_eq!(num, snail("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]"));
+
+        // Regular numbers past the old byte encoding explode and split fine
+        let mut big = snail("[[[[[91,200],1],1],1],1]");
+        assert!(big.reduce_step(&SnailRules::default()).unwrap().is_some());
+        assert_eq!(big, snail("[[[[0,201],1],1],1]"));
+    }
+
+    #[test]
+    fn reduction() {
+        // The worked example from the puzzle
+        let num = SnailNumber::pair(snail("[[[[4,3],4],4],[7,[[8,4],9]]]"), snail("[1,1]"));
+        let trace: Vec<_> = num
+            .reduction()
+            .unwrap()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            trace,
+            [
+                "explode [4,3] at root.0.0.0.0: [[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+                "explode [8,4] at root.0.1.1.0: [[[[0,7],4],[15,[0,13]]],[1,1]]",
+                "split 15 at root.0.1.0: [[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+                "split 13 at root.0.1.1.1: [[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+                "explode [6,7] at root.0.1.1.1: [[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+            ]
+        );
+        assert_eq!(
+            num.reduction().unwrap()[2].action,
+            ReduceAction::Split {
+                path: vec![0, 1, 0],
+                value: 15
+            }
+        );
+        assert_eq!(snail("[[1,2],3]").reduction(), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn sum() {
+        let list: Vec<_> = ["[1,1]", "[2,2]", "[3,3]", "[4,4]", "[5,5]", "[6,6]"]
+            .iter()
+            .map(|s| snail(s))
+            .collect();
+        let expected = snail("[[[[5,0],[7,4]],[5,5]],[6,6]]");
+        assert_eq!(list.iter().sum::<SnailNumber>(), expected);
+        assert_eq!(list.clone().into_iter().sum::<SnailNumber>(), expected);
+
+        let mut total = list[0].clone();
+        for num in &list[1..] {
+            total += num;
+        }
+        assert_eq!(total, expected);
+        assert_eq!(&list[0] + &list[1], snail("[[1,1],[2,2]]"));
+    }
+
+    #[test]
+    fn rules() {
+        let deep = snail("[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]");
+        let shallow = SnailRules {
+            explode_depth: 5,
+            ..SnailRules::default()
+        };
+        assert_eq!(deep.reduction_with(&shallow), Ok(Vec::new()));
+
+        let low = SnailRules {
+            split_threshold: 5,
+            ..SnailRules::default()
+        };
+        let sum = snail("[7,1]").add_with(snail("[2,2]"), &low);
+        assert_eq!(sum, Ok(snail("[[[3,4],1],[2,2]]")));
+
+        let even = SnailRules {
+            left_weight: 1,
+            right_weight: 1,
+            ..SnailRules::default()
+        };
+        assert_eq!(snail("[[1,2],[[3,4],5]]").magnitude_with(&even), Ok(15));
+
+        assert_eq!(SnailRules::default().max_magnitude(), Ok(9 * 5 * 5 * 5 * 5));
+        assert_eq!(shallow.max_magnitude(), Ok(9 * 5 * 5 * 5 * 5 * 5));
+        // 9 at the heaviest position, then the four of weight 54, then 54 of
+        // the 100 spread over weight 36, and the last 1 at weight 24
+        assert_eq!(
+            SnailRules::default().magnitude_bound(100),
+            Ok(9 * 81 + 36 * 54 + 54 * 36 + 24)
+        );
+        assert_eq!(SnailRules::default().magnitude_bound(4), Ok(4 * 81));
+
+        // The bound and the magnitude agree on what does not fit
+        let heavy = SnailRules {
+            left_weight: 1 << 20,
+            ..SnailRules::default()
+        };
+        assert_eq!(heavy.max_magnitude(), Err(SnailOverflow));
+        assert_eq!(heavy.magnitude_bound(1), Err(SnailOverflow));
+        assert_eq!(heavy.magnitude_bound(0), Ok(0));
+        assert_eq!(
+            snail("[[[[1,0],0],0],0]").magnitude_with(&heavy),
+            Err(SnailOverflow)
+        );
+    }
+
+    #[test]
+    fn magnitude() {
+        assert_eq!(snail("[[1,2],[[3,4],5]]").magnitude(), Ok(143));
+        assert_eq!(
+            snail("[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]").magnitude(),
+            Ok(3488)
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(
+            snail("[6148914691236517206,1]").magnitude(),
+            Err(SnailOverflow)
+        );
+        assert_eq!(snail("[6148914691236517205,0]").magnitude(), Ok(u64::MAX));
+        // Exploding [1,1] pushes its right value onto the largest u64
+        let max = snail("[[[[[1,1],18446744073709551615],2],3],4]");
+        assert_eq!(max.reduction(), Err(SnailOverflow));
+        assert_eq!(
+            snail("[[[[1,1],18446744073709551615],2],3]")
+                .add_with(snail("4"), &SnailRules::default()),
+            Err(SnailOverflow)
+        );
+    }
+}